@@ -1 +1,3 @@
+pub mod monster;
 pub mod player;
+pub mod stats;
@@ -1,11 +1,47 @@
 use crate::map::tiles::Position;
+use legion::Entity;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct IsPlayer {}
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Viewshed {
     pub visible_tiles: Vec<Position>,
     pub range: i32,
     pub dirty: bool,
 }
+
+/// Click-to-travel state: where the player is going and the steps left.
+#[derive(Debug, Default)]
+pub struct Travel {
+    pub destination: Option<Position>,
+    pub path: Vec<Position>,
+    /// Seconds left until the next step.
+    pub cooldown: f32,
+    /// The monsters in view when the previous step was taken.
+    pub monsters_in_view: HashSet<Entity>,
+}
+
+impl Travel {
+    /// Stop travelling and forget the planned path.
+    pub fn cancel(&mut self) {
+        self.destination = None;
+        self.path.clear();
+    }
+}
+
+/// Whether the player took a turn in the current tick. Most ticks pass without a turn,
+/// the player acts on a command or a step of the click-to-travel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PlayerTurn {
+    pub taken: bool,
+}
+
+/// Rules for the steps of the player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MoveRules {
+    /// Allow diagonal steps past the corner of a wall.
+    pub corner_cutting: bool,
+}
@@ -0,0 +1,49 @@
+use crate::characters::monster::Name;
+use crate::characters::player::IsPlayer;
+use crate::utils::gamelog::GameLog;
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use legion::{component, system, Entity, IntoQuery};
+use serde::{Deserialize, Serialize};
+
+/// Health of the entities that can be healed and damaged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CombatStats {
+    pub max_hp: i32,
+    pub hp: i32,
+}
+
+impl CombatStats {
+    pub const fn new(max_hp: i32) -> Self {
+        Self { max_hp, hp: max_hp }
+    }
+
+    /// Restore up to `amount` hit points, never above `max_hp`.
+    pub fn heal(&mut self, amount: i32) {
+        self.hp = (self.hp + amount).min(self.max_hp);
+    }
+
+    /// Lose up to `amount` hit points, never below 0.
+    pub fn take_damage(&mut self, amount: i32) {
+        self.hp = (self.hp - amount).max(0);
+    }
+}
+
+/// Remove every non-player entity that ran out of hit points.
+#[system]
+#[read_component(CombatStats)]
+#[read_component(IsPlayer)]
+#[read_component(Name)]
+pub fn delete_the_dead(world: &SubWorld, cmd: &mut CommandBuffer, #[resource] log: &mut GameLog) {
+    let mut query =
+        <(Entity, &CombatStats, Option<&Name>)>::query().filter(!component::<IsPlayer>());
+    for (entity, stats, name) in query.iter(world) {
+        if stats.hp <= 0 {
+            cmd.remove(*entity);
+            match name {
+                Some(monster) => log.add(format!("The {} dies.", monster.name)),
+                None => log.add("Something dies.".to_string()),
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::items::Item;
+use legion::Entity;
+use macroquad::{
+    draw_rectangle, draw_text, is_key_pressed, screen_height, screen_width, Color, KeyCode, WHITE,
+};
+
+/// Hotkeys of the listed items, in order.
+const ITEM_KEYS: [(KeyCode, char); 26] = [
+    (KeyCode::A, 'a'),
+    (KeyCode::B, 'b'),
+    (KeyCode::C, 'c'),
+    (KeyCode::D, 'd'),
+    (KeyCode::E, 'e'),
+    (KeyCode::F, 'f'),
+    (KeyCode::G, 'g'),
+    (KeyCode::H, 'h'),
+    (KeyCode::I, 'i'),
+    (KeyCode::J, 'j'),
+    (KeyCode::K, 'k'),
+    (KeyCode::L, 'l'),
+    (KeyCode::M, 'm'),
+    (KeyCode::N, 'n'),
+    (KeyCode::O, 'o'),
+    (KeyCode::P, 'p'),
+    (KeyCode::Q, 'q'),
+    (KeyCode::R, 'r'),
+    (KeyCode::S, 's'),
+    (KeyCode::T, 't'),
+    (KeyCode::U, 'u'),
+    (KeyCode::V, 'v'),
+    (KeyCode::W, 'w'),
+    (KeyCode::X, 'x'),
+    (KeyCode::Y, 'y'),
+    (KeyCode::Z, 'z'),
+];
+
+/// What the player decided to do in the inventory screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InventoryAction {
+    NoResponse,
+    Close,
+    Use(Entity),
+    Drop(Entity),
+}
+
+/// State of the inventory screen between frames.
+#[derive(Clone, Debug, Default)]
+pub struct InventoryMenu {
+    /// Index of the item the actions apply to.
+    selected: Option<usize>,
+    /// Description of the inspected item.
+    inspected: Option<String>,
+}
+
+impl InventoryMenu {
+    /// Forget the selection, used when the screen is opened again.
+    pub fn reset(&mut self) {
+        self.selected = None;
+        self.inspected = None;
+    }
+
+    /// Handle the keyboard: letters select an item, then `u`se, `d`rop or `i`nspect it.
+    /// Escape goes back to the item list or closes the screen.
+    pub fn handle_input(&mut self, items: &[(Entity, Item)]) -> InventoryAction {
+        if is_key_pressed(KeyCode::Escape) {
+            if self.selected.is_some() {
+                self.reset();
+                return InventoryAction::NoResponse;
+            }
+            return InventoryAction::Close;
+        }
+
+        match self.selected.and_then(|index| items.get(index)) {
+            Some((entity, item)) => {
+                if is_key_pressed(KeyCode::U) {
+                    self.reset();
+                    return InventoryAction::Use(*entity);
+                }
+                if is_key_pressed(KeyCode::D) {
+                    self.reset();
+                    return InventoryAction::Drop(*entity);
+                }
+                if is_key_pressed(KeyCode::I) {
+                    self.inspected = Some(item.description.clone());
+                }
+            }
+            None => {
+                self.selected = ITEM_KEYS
+                    .iter()
+                    .take(items.len())
+                    .position(|(key, _)| is_key_pressed(*key));
+            }
+        }
+        InventoryAction::NoResponse
+    }
+
+    /// Render the inventory in screen space. (after `set_default_camera()`)
+    pub fn draw(&self, items: &[(Entity, Item)]) {
+        let width = 400.0;
+        let height = (items.len().max(1) as f32).mul_add(25.0, 80.0);
+        let x = (screen_width() - width) / 2.0;
+        let mut y = (screen_height() - height) / 2.0;
+        draw_rectangle(x, y, width, height, Color([20, 20, 30, 230]));
+
+        y += 30.0;
+        draw_text("Inventory", x + 10.0, y, 24.0, WHITE);
+        let hint_color = Color([150, 150, 150, 255]);
+        if items.is_empty() {
+            draw_text(
+                "Your backpack is empty.",
+                x + 10.0,
+                y + 25.0,
+                20.0,
+                hint_color,
+            );
+        }
+        for (((_, item), (_, key)), index) in items.iter().zip(ITEM_KEYS.iter()).zip(0..) {
+            y += 25.0;
+            let color = if self.selected == Some(index) {
+                Color([230, 200, 80, 255])
+            } else {
+                WHITE
+            };
+            draw_text(&format!("({key}) {}", item.name), x + 10.0, y, 20.0, color);
+        }
+
+        let hint = if self.selected.is_some() {
+            "u: use  d: drop  i: inspect  esc: back"
+        } else {
+            "letter: select item  esc: close"
+        };
+        draw_text(hint, x + 10.0, y + 40.0, 18.0, hint_color);
+        if let Some(description) = &self.inspected {
+            draw_text(description, x + 10.0, y + 60.0, 18.0, WHITE);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::map::tiles::Position;
+use crate::utils::keymap::{key_name, Action, Keymap};
+use legion::Entity;
+use macroquad::{
+    draw_rectangle, draw_rectangle_lines, draw_text, is_key_pressed, screen_height, Color, KeyCode,
+    WHITE,
+};
+use std::cmp::max;
+
+/// What the player decided to do in the targeting mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetingAction {
+    NoResponse,
+    Cancel,
+    Confirm(Position),
+}
+
+/// Choosing the target tile for a ranged `item`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Targeting {
+    pub item: Entity,
+    /// Radius of the area of effect, 0 for single target items.
+    radius: i32,
+    /// Tiles in the player's view within the item range.
+    valid_tiles: Vec<Position>,
+    cursor: Position,
+    /// Tile under the mouse in the previous frame, the cursor follows the mouse only when it moves.
+    last_mouse_tile: Option<Position>,
+}
+
+impl Targeting {
+    /// Start targeting from `origin` with the tiles the player can see.
+    pub fn new(
+        item: Entity,
+        range: i32,
+        radius: i32,
+        origin: Position,
+        visible_tiles: &[Position],
+    ) -> Self {
+        // Steps away, the same distance the area of effect and the movement use.
+        let valid_tiles = visible_tiles
+            .iter()
+            .filter(|pos| max((pos.x - origin.x).abs(), (pos.y - origin.y).abs()) <= range)
+            .copied()
+            .collect();
+        Self {
+            item,
+            radius,
+            valid_tiles,
+            cursor: origin,
+            last_mouse_tile: None,
+        }
+    }
+
+    fn is_valid(&self, pos: Position) -> bool {
+        self.valid_tiles.contains(&pos)
+    }
+
+    /// Move the cursor with the move keys or the mouse, confirm with the confirm key or a click.
+    /// Escape cancels. The cursor never leaves the valid tiles.
+    pub fn handle_input(
+        &mut self,
+        keymap: &Keymap,
+        mouse_tile: Position,
+        clicked: bool,
+    ) -> TargetingAction {
+        if is_key_pressed(KeyCode::Escape) {
+            return TargetingAction::Cancel;
+        }
+
+        if self.last_mouse_tile != Some(mouse_tile) {
+            self.last_mouse_tile = Some(mouse_tile);
+            if self.is_valid(mouse_tile) {
+                self.cursor = mouse_tile;
+            }
+        }
+        if let Some((dx, dy)) = keymap.pressed_direction() {
+            let cursor = Position {
+                x: self.cursor.x + dx,
+                y: self.cursor.y + dy,
+            };
+            if self.is_valid(cursor) {
+                self.cursor = cursor;
+            }
+        }
+
+        let confirmed = keymap.pressed(Action::Confirm) || (clicked && mouse_tile == self.cursor);
+        if confirmed && self.is_valid(self.cursor) {
+            return TargetingAction::Confirm(self.cursor);
+        }
+        TargetingAction::NoResponse
+    }
+
+    /// Highlight the valid tiles, the area of effect and the cursor. (in camera space)
+    pub fn draw(&self) {
+        for pos in &self.valid_tiles {
+            draw_rectangle(
+                pos.x as f32,
+                pos.y as f32,
+                1.0,
+                1.0,
+                Color([80, 120, 220, 60]),
+            );
+        }
+        for dx in -self.radius..=self.radius {
+            for dy in -self.radius..=self.radius {
+                draw_rectangle(
+                    (self.cursor.x + dx) as f32,
+                    (self.cursor.y + dy) as f32,
+                    1.0,
+                    1.0,
+                    Color([220, 80, 60, 80]),
+                );
+            }
+        }
+        draw_rectangle_lines(
+            self.cursor.x as f32,
+            self.cursor.y as f32,
+            1.0,
+            1.0,
+            0.1,
+            Color([230, 200, 80, 255]),
+        );
+    }
+
+    /// Render the controls hint in screen space. (after `set_default_camera()`)
+    pub fn draw_hint(keymap: &Keymap) {
+        let confirm = keymap
+            .keys(Action::Confirm)
+            .first()
+            .map_or("?", |key| key_name(*key));
+        draw_text(
+            &format!(
+                "Select a target: move keys or mouse, {confirm} or click to confirm, esc to cancel"
+            ),
+            10.0,
+            screen_height() - 180.0,
+            20.0,
+            WHITE,
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::map::tiles::Position;
+use crate::utils::keymap::{Action, Keymap};
+use macroquad::Vec2;
+use serde::{Deserialize, Serialize};
+
+pub mod replay;
+
+/// What the player wants to do this turn. The game systems only see commands,
+/// so they can come from the keyboard and mouse as well as from a replay or a bot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlayerCommand {
+    /// Step to the neighbouring tile, y is up.
+    Move {
+        dx: i32,
+        dy: i32,
+    },
+    Wait,
+    /// Pick up the item under the player.
+    Pickup,
+    /// Walk to the tile over the revealed map, one step per turn.
+    TravelTo(Position),
+    TakeStairs,
+    /// Use the item at `slot` in the backpack, ranged items need a `target`.
+    UseItem {
+        slot: usize,
+        target: Option<Position>,
+    },
+    /// Drop the item at `slot` in the backpack.
+    DropItem {
+        slot: usize,
+    },
+}
+
+impl PlayerCommand {
+    /// Check if the player spends a turn on the command.
+    /// Travelling only sets the destination, the steps take the turns.
+    pub const fn takes_turn(self) -> bool {
+        match self {
+            Self::Move { .. }
+            | Self::Wait
+            | Self::Pickup
+            | Self::TakeStairs
+            | Self::UseItem { .. }
+            | Self::DropItem { .. } => true,
+            Self::TravelTo(_) => false,
+        }
+    }
+}
+
+/// The command of the current frame, consumed by the game systems.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerInput {
+    pub command: Option<PlayerCommand>,
+    /// Length of the frame in seconds. Systems use it instead of `get_frame_time()`,
+    /// so a replay runs with the recorded frame times.
+    pub delta: f32,
+}
+
+/// Turn the keyboard and the mouse into a command while the turn loop runs.
+/// Keys win over the mouse, only one command is read per frame.
+pub fn read_command(keymap: &Keymap, mouse_position: Vec2, clicked: bool) -> Option<PlayerCommand> {
+    if keymap.pressed(Action::Wait) {
+        return Some(PlayerCommand::Wait);
+    }
+    if let Some((dx, dy)) = keymap.pressed_direction() {
+        return Some(PlayerCommand::Move { dx, dy });
+    }
+    if keymap.pressed(Action::Pickup) {
+        return Some(PlayerCommand::Pickup);
+    }
+    if keymap.pressed(Action::TakeStairs) {
+        return Some(PlayerCommand::TakeStairs);
+    }
+    if clicked {
+        return Some(PlayerCommand::TravelTo(Position::from(mouse_position)));
+    }
+    None
+}
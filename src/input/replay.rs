@@ -0,0 +1,224 @@
+use crate::input::PlayerCommand;
+use crate::raws::raws;
+use crate::utils::settings::Settings;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::Write;
+
+/// Version of the recording format, recordings of other versions can't be replayed.
+const VERSION: u32 = 2;
+
+/// First line of a recording.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    /// Seed of the random generator the run started with.
+    seed: u64,
+    settings: RunSettings,
+}
+
+/// The settings that change how the run plays, a replay uses the recorded ones.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunSettings {
+    pub width: usize,
+    pub height: usize,
+    pub generator: String,
+    pub gen_param: i32,
+    pub corner_cutting: bool,
+    /// Checksum of the raws, they can't be taken from the recording, only checked.
+    pub raws: u64,
+}
+
+impl RunSettings {
+    /// Get the settings of a run with the loaded raws.
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            width: settings.width,
+            height: settings.height,
+            generator: settings.generator.clone(),
+            gen_param: settings.gen_param,
+            corner_cutting: settings.corner_cutting,
+            raws: raws().checksum,
+        }
+    }
+
+    /// Replace the `settings` with the recorded ones.
+    /// Returns an error if the loaded raws are not the recorded ones, the replay would go astray.
+    pub fn apply(&self, settings: &mut Settings) -> Result<(), String> {
+        settings.width = self.width;
+        settings.height = self.height;
+        settings.generator.clone_from(&self.generator);
+        settings.gen_param = self.gen_param;
+        settings.corner_cutting = self.corner_cutting;
+        if self.raws == raws().checksum {
+            Ok(())
+        } else {
+            Err("the recording was made with other raws".to_string())
+        }
+    }
+}
+
+/// One run of the turn schedule: the frame time and the command of the player.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tick {
+    pub delta: f32,
+    pub command: Option<PlayerCommand>,
+}
+
+/// Writes the ticks of the current run to a file, one JSON line each.
+/// Every line is written right away, so the recording survives a crash.
+#[derive(Debug)]
+pub struct Recorder {
+    file: File,
+}
+
+impl Recorder {
+    /// Create the recording file for a run started with `seed` and `settings`.
+    pub fn create(path: &str, seed: u64, settings: RunSettings) -> Result<Self, String> {
+        let mut file = File::create(path).map_err(|e| e.to_string())?;
+        let header = serde_json::to_string(&Header {
+            version: VERSION,
+            seed,
+            settings,
+        })
+        .map_err(|e| e.to_string())?;
+        writeln!(file, "{header}").map_err(|e| e.to_string())?;
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, tick: Tick) -> Result<(), String> {
+        let line = serde_json::to_string(&tick).map_err(|e| e.to_string())?;
+        writeln!(self.file, "{line}").map_err(|e| e.to_string())
+    }
+}
+
+/// A recorded run played back tick by tick.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub settings: RunSettings,
+    ticks: VecDeque<Tick>,
+}
+
+impl Replay {
+    /// Load a recording. A broken last line, e.g. from a crash while writing it, is skipped.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut lines = contents.lines();
+        let header: Header = serde_json::from_str(lines.next().unwrap_or_default())
+            .map_err(|e| format!("invalid header: {e}"))?;
+        if header.version != VERSION {
+            return Err(format!(
+                "recording version {} is not supported, expected {}",
+                header.version, VERSION
+            ));
+        }
+        let tick_lines: Vec<&str> = lines.collect();
+        let mut ticks = VecDeque::with_capacity(tick_lines.len());
+        for (i, line) in tick_lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(tick) => ticks.push_back(tick),
+                Err(_) if i + 1 == tick_lines.len() => break,
+                Err(e) => return Err(format!("invalid tick on line {}: {}", i + 2, e)),
+            }
+        }
+        Ok(Self {
+            seed: header.seed,
+            settings: header.settings,
+            ticks,
+        })
+    }
+
+    pub fn next_tick(&mut self) -> Option<Tick> {
+        self.ticks.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::tiles::Position;
+    use crate::simulation::tests::{snapshot, SEED};
+    use crate::simulation::{Simulation, TURN_DELTA};
+
+    fn temp_path(name: &str) -> String {
+        let file =
+            std::env::temp_dir().join(format!("roguelike-{}-{}.jsonl", name, std::process::id()));
+        file.to_str().expect("temporary path").to_string()
+    }
+
+    #[test]
+    fn replay_plays_the_recorded_run() {
+        let mut settings = Settings::default();
+        settings.width = 30;
+        settings.height = 30;
+        settings.corner_cutting = !settings.corner_cutting;
+        let commands = [
+            Some(PlayerCommand::Move { dx: 1, dy: 0 }),
+            Some(PlayerCommand::Wait),
+            Some(PlayerCommand::Move { dx: 0, dy: -1 }),
+            Some(PlayerCommand::TravelTo(Position { x: 10, y: 10 })),
+            None,
+            None,
+            Some(PlayerCommand::Pickup),
+            Some(PlayerCommand::Move { dx: -1, dy: 1 }),
+        ];
+
+        let path = temp_path("replay");
+        fastrand::seed(SEED);
+        let mut original = Simulation::new(settings.clone());
+        let mut recorder =
+            Recorder::create(&path, SEED, RunSettings::new(&settings)).expect("recording");
+        for command in commands {
+            let tick = Tick {
+                delta: TURN_DELTA,
+                command,
+            };
+            recorder.record(tick).expect("recorded tick");
+            original.step(tick.command, tick.delta);
+        }
+        drop(recorder);
+
+        let mut replay = Replay::load(&path).expect("loaded replay");
+        fs::remove_file(&path).ok();
+        // The replay brings its own settings, the local ones don't matter.
+        let mut replayed_settings = Settings::default();
+        replay
+            .settings
+            .apply(&mut replayed_settings)
+            .expect("same raws");
+        fastrand::seed(replay.seed);
+        let mut played_back = Simulation::new(replayed_settings);
+        while let Some(tick) = replay.next_tick() {
+            played_back.step(tick.command, tick.delta);
+        }
+        assert_eq!(snapshot(&played_back), snapshot(&original));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let path = temp_path("old-replay");
+        let header = Header {
+            version: VERSION - 1,
+            seed: SEED,
+            settings: RunSettings::new(&Settings::default()),
+        };
+        let contents = serde_json::to_string(&header).expect("header");
+        fs::write(&path, contents).expect("written recording");
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).ok();
+        assert!(loaded.is_err(), "{:?}", loaded);
+    }
+
+    #[test]
+    fn other_raws_are_rejected() {
+        let mut settings = Settings::default();
+        let other_raws = RunSettings {
+            raws: raws().checksum.wrapping_add(1),
+            ..RunSettings::new(&settings)
+        };
+        assert!(other_raws.apply(&mut settings).is_err());
+        assert!(RunSettings::new(&settings).apply(&mut settings).is_ok());
+    }
+}
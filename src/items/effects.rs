@@ -0,0 +1,168 @@
+use crate::characters::player::{IsPlayer, PlayerTurn};
+use crate::characters::stats::CombatStats;
+use crate::items::{Item, WantsToUseItem};
+use crate::map::tiles::Position;
+use crate::utils::gamelog::GameLog;
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use legion::{system, Entity, EntityStore, IntoQuery};
+use serde::{Deserialize, Serialize};
+
+/// The item is gone after one use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Consumable {}
+
+/// Using the item restores hit points of the targets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvidesHealing {
+    pub heal_amount: i32,
+}
+
+/// Using the item hurts the targets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InflictsDamage {
+    pub damage: i32,
+}
+
+/// The item is used on a tile chosen in the targeting mode, up to `range` tiles away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ranged {
+    pub range: i32,
+}
+
+/// The item affects everything within `radius` tiles of the target, not only the target tile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AreaOfEffect {
+    pub radius: i32,
+}
+
+/// Using the item confuses the targets for some `turns`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Confusion {
+    pub turns: i32,
+}
+
+/// Status of a confused entity, `turns` left until it comes to its senses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Confused {
+    pub turns: i32,
+}
+
+/// Check if `pos` is within `radius` tiles of the `center`.
+const fn in_radius(center: Position, pos: Position, radius: i32) -> bool {
+    (pos.x - center.x).abs() <= radius && (pos.y - center.y).abs() <= radius
+}
+
+/// Get the entities with `CombatStats` affected by the item used on `center`.
+fn affected_entities(world: &SubWorld, center: Position, aoe: Option<AreaOfEffect>) -> Vec<Entity> {
+    let radius = aoe.map_or(0, |area| area.radius);
+    <(Entity, &Position, &CombatStats)>::query()
+        .iter(world)
+        .filter(|(_, pos, _)| in_radius(center, **pos, radius))
+        .map(|(entity, _, _)| *entity)
+        .collect()
+}
+
+/// Apply the effect components of the used items to their targets.
+/// Items without a target are used on the user themselves.
+#[system]
+#[read_component(WantsToUseItem)]
+#[read_component(Item)]
+#[read_component(Position)]
+#[read_component(Consumable)]
+#[read_component(ProvidesHealing)]
+#[read_component(InflictsDamage)]
+#[read_component(AreaOfEffect)]
+#[read_component(Confusion)]
+#[write_component(CombatStats)]
+pub fn use_item(world: &mut SubWorld, cmd: &mut CommandBuffer, #[resource] log: &mut GameLog) {
+    let wants: Vec<(Entity, WantsToUseItem)> = <(Entity, &WantsToUseItem)>::query()
+        .iter(world)
+        .map(|(entity, want)| (*entity, *want))
+        .collect();
+    for (intent, want) in wants {
+        cmd.remove(intent);
+        let Ok(item) = world.entry_ref(want.item) else {
+            continue;
+        };
+        let name = item
+            .get_component::<Item>()
+            .map_or_else(|_| "something".to_string(), |it| it.name.clone());
+        let consumable = item.get_component::<Consumable>().is_ok();
+        let healing = item.get_component::<ProvidesHealing>().ok().copied();
+        let damage = item.get_component::<InflictsDamage>().ok().copied();
+        let aoe = item.get_component::<AreaOfEffect>().ok().copied();
+        let confusion = item.get_component::<Confusion>().ok().copied();
+        if healing.is_none() && damage.is_none() && confusion.is_none() {
+            log.add(format!("You don't know how to use {name}."));
+            continue;
+        }
+
+        let center = match want.target {
+            Some(target) => target,
+            None => match world
+                .entry_ref(want.user)
+                .ok()
+                .and_then(|user| user.get_component::<Position>().ok().copied())
+            {
+                Some(pos) => pos,
+                None => continue,
+            },
+        };
+        log.add(format!("You use {name}."));
+        for target in affected_entities(world, center, aoe) {
+            let is_user = target == want.user;
+            let Ok(mut entry) = world.entry_mut(target) else {
+                continue;
+            };
+            let Ok(stats) = entry.get_component_mut::<CombatStats>() else {
+                continue;
+            };
+            if let Some(ProvidesHealing { heal_amount }) = healing {
+                stats.heal(heal_amount);
+                if is_user {
+                    log.add(format!("You recover {heal_amount} hp."));
+                }
+            }
+            if let Some(InflictsDamage { damage: amount }) = damage {
+                stats.take_damage(amount);
+                if is_user {
+                    log.add(format!("You are caught in the blast for {amount} damage."));
+                } else {
+                    log.add(format!("It hits for {amount} damage."));
+                }
+            }
+            if let Some(Confusion { turns }) = confusion {
+                cmd.add_component(target, Confused { turns });
+                if is_user {
+                    log.add("You feel confused.".to_string());
+                }
+            }
+        }
+        if consumable {
+            cmd.remove(want.item);
+        }
+    }
+}
+
+/// Count down the confusion once per turn of the player, it wears off at 0 turns.
+#[system(for_each)]
+pub fn wear_off_confusion(
+    entity: &Entity,
+    confused: &mut Confused,
+    player: Option<&IsPlayer>,
+    cmd: &mut CommandBuffer,
+    #[resource] turn: &PlayerTurn,
+    #[resource] log: &mut GameLog,
+) {
+    if !turn.taken {
+        return;
+    }
+    confused.turns -= 1;
+    if confused.turns <= 0 {
+        cmd.remove_component::<Confused>(*entity);
+        if player.is_some() {
+            log.add("You are no longer confused.".to_string());
+        }
+    }
+}
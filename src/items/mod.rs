@@ -0,0 +1,237 @@
+pub mod effects;
+
+use crate::characters::player::IsPlayer;
+use crate::input::{PlayerCommand, PlayerInput};
+use crate::map::tiles::{Position, Tile};
+use crate::raws::raws;
+use crate::utils::gamelog::GameLog;
+use fastrand::Rng;
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use legion::{component, system, Entity, EntityStore, IntoQuery};
+use serde::{Deserialize, Serialize};
+
+/// Something that can lie on the ground and be picked up.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Item {
+    pub name: String,
+    pub description: String,
+}
+
+/// The item is carried by the `owner` instead of lying on the map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InBackpack {
+    pub owner: Entity,
+}
+
+/// Intent to move the `item` into the backpack of `collected_by`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WantsToPickupItem {
+    pub collected_by: Entity,
+    pub item: Entity,
+}
+
+/// Intent of the `user` to use the `item` from their backpack on the `target` tile.
+/// Without a target the item is used on the user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WantsToUseItem {
+    pub user: Entity,
+    pub item: Entity,
+    pub target: Option<Position>,
+}
+
+/// Intent of the `dropper` to put the `item` from their backpack on the ground.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WantsToDropItem {
+    pub dropper: Entity,
+    pub item: Entity,
+}
+
+/// Coins are not stored in the backpack, they are added to the `Purse`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gold {
+    pub amount: u32,
+}
+
+/// The gold counter of an entity.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Purse {
+    pub gold: u32,
+}
+
+/// Open the chest at `chest` and spill random loot into the adjacent walkable tiles.
+/// Returns the number of spawned items.
+pub fn spill_chest(cmd: &mut CommandBuffer, map: &mut Vec<Vec<Tile>>, chest: Position) -> usize {
+    const MAX_LOOT: usize = 4;
+    let rng = Rng::new();
+    map[chest.x as usize][chest.y as usize] = Tile::GRASS;
+
+    let mut free_tiles: Vec<Position> = Vec::new();
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            let pos = Position {
+                x: chest.x + dx,
+                y: chest.y + dy,
+            };
+            let walkable = map
+                .get(pos.x as usize)
+                .and_then(|row| row.get(pos.y as usize))
+                .is_some_and(|tile| tile.is_walkable());
+            if walkable {
+                free_tiles.push(pos);
+            }
+        }
+    }
+    rng.shuffle(&mut free_tiles);
+
+    let loot_count = rng.usize(1..=MAX_LOOT).min(free_tiles.len());
+    for pos in free_tiles.into_iter().take(loot_count) {
+        if let Some(name) = raws().roll_chest_loot(&rng) {
+            raws().spawn_named(cmd, name, pos);
+        }
+    }
+    loot_count
+}
+
+/// Get the items carried by the `owner`, the backpack slots are in this order.
+pub fn backpack<W: EntityStore>(world: &W, owner: Entity) -> Vec<(Entity, Item)> {
+    <(Entity, &Item, &InBackpack)>::query()
+        .iter(world)
+        .filter(|(_, _, backpack)| backpack.owner == owner)
+        .map(|(entity, item, _)| (*entity, item.clone()))
+        .collect()
+}
+
+/// Handle the pickup command: the player wants to pick up an item lying under them.
+#[system]
+#[read_component(Position)]
+#[read_component(Item)]
+#[read_component(IsPlayer)]
+pub fn pickup_item(
+    world: &SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] log: &mut GameLog,
+    #[resource] input: &PlayerInput,
+) {
+    if input.command != Some(PlayerCommand::Pickup) {
+        return;
+    }
+    let mut players = <(Entity, &Position)>::query().filter(component::<IsPlayer>());
+    let mut items = <(Entity, &Position)>::query().filter(component::<Item>());
+    for (player, player_pos) in players.iter(world) {
+        match items.iter(world).find(|(_, pos)| *pos == player_pos) {
+            Some((item, _)) => {
+                cmd.push((WantsToPickupItem {
+                    collected_by: *player,
+                    item: *item,
+                },));
+            }
+            None => log.add("There is nothing here to pick up.".to_string()),
+        }
+    }
+}
+
+/// Turn the use and drop commands into intents for the item in the backpack slot of the player.
+#[system]
+#[read_component(Item)]
+#[read_component(InBackpack)]
+#[read_component(IsPlayer)]
+pub fn item_commands(world: &SubWorld, cmd: &mut CommandBuffer, #[resource] input: &PlayerInput) {
+    let (slot, target, drop) = match input.command {
+        Some(PlayerCommand::UseItem { slot, target }) => (slot, target, false),
+        Some(PlayerCommand::DropItem { slot }) => (slot, None, true),
+        _ => return,
+    };
+    let players: Vec<Entity> = <Entity>::query()
+        .filter(component::<IsPlayer>())
+        .iter(world)
+        .copied()
+        .collect();
+    for player in players {
+        let Some((item, _)) = backpack(world, player).get(slot).cloned() else {
+            continue;
+        };
+        if drop {
+            cmd.push((WantsToDropItem {
+                dropper: player,
+                item,
+            },));
+        } else {
+            cmd.push((WantsToUseItem {
+                user: player,
+                item,
+                target,
+            },));
+        }
+    }
+}
+
+/// Move the wanted items into the backpacks, coins go straight to the `Purse`.
+#[system]
+#[read_component(WantsToPickupItem)]
+#[read_component(Item)]
+#[read_component(Gold)]
+#[write_component(Purse)]
+pub fn item_collection(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] log: &mut GameLog,
+) {
+    let wants: Vec<(Entity, WantsToPickupItem)> = <(Entity, &WantsToPickupItem)>::query()
+        .iter(world)
+        .map(|(entity, want)| (*entity, *want))
+        .collect();
+    for (intent, want) in wants {
+        cmd.remove(intent);
+        let Ok(entry) = world.entry_ref(want.item) else {
+            continue;
+        };
+        let name = entry
+            .get_component::<Item>()
+            .map_or_else(|_| "something".to_string(), |item| item.name.clone());
+        let gold = entry.get_component::<Gold>().ok().copied();
+
+        if let Some(Gold { amount }) = gold {
+            if let Ok(mut collector) = world.entry_mut(want.collected_by) {
+                if let Ok(purse) = collector.get_component_mut::<Purse>() {
+                    purse.gold += amount;
+                }
+            }
+            cmd.remove(want.item);
+        } else {
+            cmd.remove_component::<Position>(want.item);
+            cmd.add_component(
+                want.item,
+                InBackpack {
+                    owner: want.collected_by,
+                },
+            );
+        }
+        log.add(format!("You pick up {name}."));
+    }
+}
+
+/// Put the items the droppers want to get rid of on the ground under them.
+#[system]
+#[read_component(WantsToDropItem)]
+#[read_component(Item)]
+#[read_component(Position)]
+pub fn drop_item(world: &SubWorld, cmd: &mut CommandBuffer, #[resource] log: &mut GameLog) {
+    for (intent, want) in <(Entity, &WantsToDropItem)>::query().iter(world) {
+        cmd.remove(*intent);
+        let Some(pos) = world
+            .entry_ref(want.dropper)
+            .ok()
+            .and_then(|dropper| dropper.get_component::<Position>().ok().copied())
+        else {
+            continue;
+        };
+        cmd.remove_component::<InBackpack>(want.item);
+        cmd.add_component(want.item, pos);
+        if let Ok(entry) = world.entry_ref(want.item) {
+            if let Ok(item) = entry.get_component::<Item>() {
+                log.add(format!("You drop {}.", item.name));
+            }
+        }
+    }
+}
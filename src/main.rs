@@ -33,271 +33,574 @@
     clippy::trivially_copy_pass_by_ref
 )]
 
-use legion::{system, Resources, Schedule, World};
+use legion::{
+    component, system, world::SubWorld, Entity, EntityStore, IntoQuery, Resources, Schedule, World,
+};
 
 use macroquad::{
-    clear_background, debug, draw_circle, draw_text, is_key_pressed, is_mouse_button_down,
-    load_texture, next_frame, set_camera, set_default_camera, warn, Camera2D, Color, KeyCode,
-    MouseButton, Vec2, BLACK, GRAY, WHITE,
+    clear_background, draw_circle, draw_rectangle, draw_text, error, get_frame_time, info,
+    is_mouse_button_down, next_frame, screen_height, set_camera, set_default_camera, warn, Color,
+    MouseButton, Vec2, Window, BLACK, WHITE,
 };
 
 mod map;
-use crate::map::generators::perlin_noise_map;
-use crate::map::tiles::{Position, Tile, TileAtlas};
-use crate::map::Rect;
+use crate::map::atlas::SpriteManager;
+use crate::map::autotile::AutotileMasks;
+use crate::map::dungeon::Dungeon;
+use crate::map::render_cache::MapRenderCache;
+use crate::map::tiles::{Position, Sprite, SpriteLayer, Tile, Tint};
 
 mod characters;
-use crate::characters::player::{IsPlayer, Viewshed};
+use crate::characters::player::{IsPlayer, Travel, Viewshed};
+use crate::characters::stats::CombatStats;
+
+mod items;
+use crate::items::{
+    backpack,
+    effects::{AreaOfEffect, Ranged},
+    Purse,
+};
+
+mod raws;
+mod spawner;
+
+mod input;
+use crate::input::replay::{Recorder, Replay, RunSettings, Tick};
+use crate::input::{read_command, PlayerCommand};
+
+mod simulation;
+use crate::simulation::save::{load_game, save_game};
+use crate::simulation::{Simulation, TURN_DELTA};
+
+mod gui;
+use crate::gui::inventory::{InventoryAction, InventoryMenu};
+use crate::gui::targeting::{Targeting, TargetingAction};
 
 mod utils;
+use utils::args::Args;
+use utils::gamelog::GameLog;
+use utils::keymap::{Action, Keymap};
 use utils::settings::Settings;
 use utils::{camera, camera::relative_mouse_position, camera::Camera};
 
-use std::cmp::max;
-#[macroquad::main("kiriRoguelike")]
-async fn main() {
+use std::iter;
+
+/// What the main loop is doing right now.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RunState {
+    /// The turn loop is running.
+    Running,
+    /// The inventory screen is open, the turn loop is paused.
+    ShowInventory,
+    /// Choosing the target of a ranged item, the turn loop is paused.
+    ShowTargeting(Targeting),
+    /// The game is saved and the window closes.
+    Quit,
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.headless {
+        run_headless(&args);
+    } else {
+        Window::new("kiriRoguelike", run_window(args));
+    }
+}
+
+/// Run the game in a window: read the input, step the simulation and draw it.
+async fn run_window(args: Args) {
     // Load settings file.
-    let settings = Settings::init("Settings.config");
-
-    // Init world and resources of legion ECS.
-    let mut world = World::default();
-    let mut resources = Resources::default();
-    let mut schedule = Schedule::builder()
-        .add_system(update_viewshed_system())
-        .add_system(handle_keyboard_system())
+    let mut settings = Settings::init("Settings.config");
+    // Load monster, item and spawn definitions.
+    raws::init("assets/raws.json");
+    // Seed the random generator before the first level is generated.
+    let (mut replay, mut recording) = start_run(&args, &mut settings);
+
+    // The world, its resources and the turn schedule.
+    let mut sim = start_simulation(&args, settings.clone());
+    sim.resources.insert(settings.keymap.clone());
+    sim.resources.insert(MapRenderCache::default());
+    // Rendering runs even when the turn loop is paused.
+    let mut render_schedule = Schedule::builder()
         .add_system(draw_system())
+        .add_system(draw_travel_path_system())
+        .add_system(draw_entities_system())
         .build();
 
     // Load assets.
-    let texture = load_texture("assets/Tiles.png").await;
-
-    // Construct TileAtlas.
-    let atlas = TileAtlas::new(texture, 32., 32.);
-    resources.insert(atlas);
+    let sprites = SpriteManager::load(&settings.atlases, settings.render_mode).await;
+    sim.resources.insert(sprites);
     // We need to save the state of the mouse button
     // to detect mouse clicks and not just "is pressed"
     let mut left_mouse_pressed = false;
 
-    // Tile is an enum of tile types, like Wall, Grass, Pengu.
-    // `rooms_map()` is a generator for the level. (There are
-    // different types of generators)
-    println!(
-        "generating the map {}:{} size",
-        settings.width, settings.height
-    );
-    let map = perlin_noise_map(settings.width, settings.height, 0.25f64);
-    // We push that map into the world, to draw it with `draw_system()`
-    resources.insert(map.tiles);
-    resources.insert(map.revealed_tiles);
-
-    // Starting position is the center of the last room.
-    let starting_position = Position::from(
-        map.rooms
-            .unwrap_or_default()
-            .last()
-            .unwrap_or(&Rect::default())
-            .center(),
-    );
-    // Insert the player into the world.
-    world.push((
-        Tile::Pengu,
-        starting_position,
-        IsPlayer {},
-        Viewshed {
-            visible_tiles: Vec::new(),
-            range: 8,
-            dirty: true,
-        },
-    ));
-
     // Initialize main camera.
     let mut main_camera = Camera::default();
-    main_camera.set_target(starting_position.into());
+    if let Some(starting_position) = sim.player_position() {
+        main_camera.set_target(starting_position.into());
+    }
+    main_camera.mode = settings.camera_follow;
+    // The key hints only change with the settings.
+    let hints = settings.keymap.hints();
+    let mut run_state = RunState::Running;
+    let mut inventory_menu = InventoryMenu::default();
     // The infinite game loop.
     loop {
         // ===========Input===========
         // Get the mouse position inside the game world.
         let mouse_position = relative_mouse_position(&main_camera);
-        left_mouse_pressed = handle_mouse(left_mouse_pressed, mouse_position);
+        let clicked = is_mouse_button_down(MouseButton::Left) && !left_mouse_pressed;
+        left_mouse_pressed = is_mouse_button_down(MouseButton::Left);
+        // A replay drives the turn loop until it ends, the menus stay closed.
+        let replayed = next_replay_tick(&mut replay, &sim.resources);
+        let (next_state, command) = replayed.map_or_else(
+            || {
+                handle_run_state(
+                    run_state,
+                    &sim.world,
+                    &sim.resources,
+                    &mut inventory_menu,
+                    sim.player,
+                    mouse_position,
+                    clicked,
+                )
+            },
+            |tick| (RunState::Running, tick.command),
+        );
+        run_state = next_state;
+        if run_state == RunState::Quit {
+            if let Err(e) = save_game(&sim, args.save_path()) {
+                error!(
+                    "Unable to save the game at {}! Error: {}",
+                    args.save_path(),
+                    e
+                );
+            }
+            // The window closes once the main future returns.
+            return;
+        }
+        let delta = replayed.map_or_else(get_frame_time, |tick| tick.delta);
 
         // ===========Update===========
-        // Checks for input related to camera and changes it accordingly.
-        camera::scroll(&mut main_camera, settings.scroll_speed, settings.zoom_speed);
+        if run_state == RunState::Running {
+            // Checks for input related to camera and changes it accordingly.
+            handle_camera_input(&mut main_camera, &sim.resources, &settings);
+            if let Some(recorder) = &mut recording {
+                if let Err(e) = recorder.record(Tick { delta, command }) {
+                    error!("Unable to record the turn! Error: {}", e);
+                }
+            }
+            if let Some(arrival) = sim.step(command, delta) {
+                main_camera.set_target(arrival.into());
+            }
+        }
+        follow_player(&mut main_camera, &sim);
 
         // ===========Draw===========
         // Fill the canvas with white.
         clear_background(BLACK);
 
         // --- Camera space, render game objects.
-        let (target, zoom) = main_camera.get();
-        set_camera(Camera2D {
-            target,
-            zoom,
-            ..macroquad::Camera2D::default()
-        });
+        set_camera(main_camera.camera2d());
 
         // ----------ECS schedule exec---------------
-        schedule.execute(&mut world, &mut resources);
-
-        // Draw the mouse cursor.
-        draw_circle(
-            mouse_position.x(),
-            mouse_position.y(),
-            0.1,
-            Color([100, 75, 120, 255]),
-        );
+        sim.resources.insert(main_camera);
+        render_schedule.execute(&mut sim.world, &mut sim.resources);
+        if let Some(mut sprites) = sim.resources.get_mut::<SpriteManager>() {
+            sprites.draw_glyphs(main_camera.camera2d());
+        }
+        draw_cursor(&run_state, mouse_position);
 
         // --- Fixed screen space, render ui.
         set_default_camera();
-        draw_ui();
+        draw_screen(&sim, &hints, &run_state, &inventory_menu);
 
         next_frame().await
     }
 }
 
-/// Calculate the viewshed.
-#[system(for_each)]
-fn update_viewshed(
-    viewshed: &mut Viewshed,
-    origin: &Position,
-    _: &IsPlayer,
-    #[resource] map: &Vec<Vec<Tile>>,
-    #[resource] revealed_tiles: &mut Vec<Vec<bool>>,
-) {
-    use symmetric_shadowcasting::compute_fov;
-    if viewshed.dirty {
-        viewshed.visible_tiles.clear();
-        let mut is_blocking = |pos: (isize, isize)| {
-            let outside = (pos.1 as usize) >= map.len() || (pos.0 as usize) >= map[0].len();
-            outside || map[pos.0 as usize][pos.1 as usize].is_opaque()
-        };
-
-        let mut mark_visible = |pos: (isize, isize)| {
-            let outside = (pos.1 as usize) >= map.len() || (pos.0 as usize) >= map[0].len();
-            let in_range = (pos.1 as usize) >= max(origin.y - viewshed.range, 0) as usize
-                && (pos.1 as usize) <= (origin.y + viewshed.range) as usize
-                && (pos.0 as usize) >= max(origin.x - viewshed.range, 0) as usize
-                && (pos.0 as usize) <= (origin.x + viewshed.range) as usize;
-            let tile_pos = Position {
-                x: pos.0 as i32,
-                y: pos.1 as i32,
-            };
-            if in_range && !outside && !viewshed.visible_tiles.contains(&tile_pos) {
-                viewshed.visible_tiles.push(tile_pos);
-                revealed_tiles[tile_pos.x as usize][tile_pos.y as usize] = true;
+/// Run the game without a window: step the replay and then `--ticks` ticks without commands,
+/// then print where the player got.
+fn run_headless(args: &Args) {
+    let mut settings = Settings::init("Settings.config");
+    raws::init("assets/raws.json");
+    let (mut replay, mut recording) = start_run(args, &mut settings);
+    let mut sim = start_simulation(args, settings);
+
+    let replayed = iter::from_fn(|| replay.as_mut()?.next_tick());
+    let idle = iter::repeat_n(
+        Tick {
+            delta: TURN_DELTA,
+            command: None,
+        },
+        args.ticks,
+    );
+    for tick in replayed.chain(idle) {
+        if let Some(recorder) = &mut recording {
+            if let Err(e) = recorder.record(tick) {
+                error!("Unable to record the turn! Error: {}", e);
+            }
+        }
+        sim.step(tick.command, tick.delta);
+    }
+
+    println!("Turns: {}", sim.turn);
+    if let Some(dungeon) = sim.resources.get::<Dungeon>() {
+        println!("Depth: {}", dungeon.depth + 1);
+    }
+    if let Some(pos) = sim.player_position() {
+        println!("Position: {}, {}", pos.x, pos.y);
+    }
+    let status = <(&Purse, &CombatStats)>::query()
+        .filter(component::<IsPlayer>())
+        .iter(&sim.world)
+        .next()
+        .map(|(purse, stats)| (*purse, *stats));
+    if let Some((purse, stats)) = status {
+        println!("HP: {}/{}  Gold: {}", stats.hp, stats.max_hp, purse.gold);
+    }
+    let log = sim.resources.get::<GameLog>();
+    for message in log.iter().flat_map(|entries| entries.latest(10)) {
+        println!("{message}");
+    }
+}
+
+/// Seed the random generator with the seed of the replay, the `--seed` option or a random one,
+/// and open the replay and the recording.
+fn start_run(args: &Args, settings: &mut Settings) -> (Option<Replay>, Option<Recorder>) {
+    let replay = args
+        .replay
+        .as_deref()
+        .and_then(|path| match Replay::load(path) {
+            Ok(loaded) => Some(loaded),
+            Err(e) => {
+                error!("Unable to load the replay at {}! Error: {}", path, e);
+                None
             }
-        };
+        });
+    // The replay is played with the settings it was recorded with.
+    if let Some(loaded) = &replay {
+        if let Err(e) = loaded.settings.apply(settings) {
+            warn!("The replay may play differently! {}", e);
+        }
+    }
+    let seed = replay
+        .as_ref()
+        .map(|loaded| loaded.seed)
+        .or(args.seed)
+        .unwrap_or_else(|| fastrand::u64(..));
+    fastrand::seed(seed);
+    info!("Seed: {}", seed);
+    if args.load.is_some() && args.record.is_some() {
+        warn!("A recording starts with a new game, --record is ignored with --load");
+    }
+    let recorder = args
+        .record
+        .as_deref()
+        .filter(|_| args.load.is_none())
+        .and_then(
+            |path| match Recorder::create(path, seed, RunSettings::new(settings)) {
+                Ok(created) => Some(created),
+                Err(e) => {
+                    error!("Unable to create the recording at {}! Error: {}", path, e);
+                    None
+                }
+            },
+        );
+    (replay, recorder)
+}
+
+/// Go on with the saved game of `--load`, or start a new one.
+/// A replay always starts with a new game.
+fn start_simulation(args: &Args, settings: Settings) -> Simulation {
+    let Some(path) = args.load.as_deref() else {
+        return Simulation::new(settings);
+    };
+    if args.replay.is_some() {
+        warn!("A replay starts with a new game, --load is ignored with --replay");
+        return Simulation::new(settings);
+    }
+    match load_game(path, settings.clone()) {
+        Ok(loaded) => {
+            info!("Loaded the game from {}", path);
+            loaded
+        }
+        Err(e) => {
+            error!("Unable to load the game at {}! Error: {}", path, e);
+            Simulation::new(settings)
+        }
+    }
+}
 
-        let (ox, oy) = origin.as_tuple();
-        let orig = (ox as isize, oy as isize);
-        compute_fov(orig, &mut is_blocking, &mut mark_visible);
+/// Get the next tick of the replay, the replay is dropped once it ends.
+fn next_replay_tick(replay: &mut Option<Replay>, resources: &Resources) -> Option<Tick> {
+    let tick = replay.as_mut()?.next_tick();
+    if tick.is_none() {
+        *replay = None;
+        if let Some(mut log) = resources.get_mut::<GameLog>() {
+            log.add("The replay has ended.".to_string());
+        }
     }
+    tick
 }
 
-// Render the map and then the in-game entities.
+/// Scroll and zoom the camera, cycle the follow mode.
+fn handle_camera_input(main_camera: &mut Camera, resources: &Resources, settings: &Settings) {
+    if settings.keymap.pressed(Action::CameraMode) {
+        main_camera.mode = main_camera.mode.next();
+        if let Some(mut log) = resources.get_mut::<GameLog>() {
+            log.add(format!("The camera is {} now.", main_camera.mode.name()));
+        }
+    }
+    camera::scroll(
+        main_camera,
+        &settings.keymap,
+        settings.scroll_speed,
+        settings.zoom_speed,
+    );
+}
+
+/// Move the camera after the player and keep it over the map.
+fn follow_player(main_camera: &mut Camera, sim: &Simulation) {
+    if let Some(pos) = sim.player_position() {
+        main_camera.follow(pos, get_frame_time());
+    }
+    if let Some(map) = sim.resources.get::<Vec<Vec<Tile>>>() {
+        main_camera.clamp_to_map(map.len(), map.first().map_or(0, Vec::len));
+    }
+}
+
+/// Render the revealed part of the map from the `MapRenderCache`,
+/// the dirty tiles the camera can see are drawn into it first.
 #[system(for_each)]
+#[filter(component::<IsPlayer>())]
 fn draw(
-    pos: &mut Position,
-    tile: &Tile,
-    _: &IsPlayer,
     viewshed: &Viewshed,
     #[resource] map: &Vec<Vec<Tile>>,
     #[resource] revealed_tiles: &Vec<Vec<bool>>,
-    #[resource] atlas: &TileAtlas,
+    #[resource] sprites: &mut SpriteManager,
+    #[resource] masks: &AutotileMasks,
+    #[resource] camera: &Camera,
+    #[resource] cache: &mut MapRenderCache,
 ) {
-    for (x, row) in map.iter().enumerate() {
-        for (y, map_tile) in row.iter().enumerate() {
-            if revealed_tiles[x][y] {
-                if viewshed.visible_tiles.contains(&Position {
-                    x: x as i32,
-                    y: y as i32,
-                }) {
-                    atlas.draw_tile(
-                        map_tile,
-                        &Position {
-                            x: x as i32,
-                            y: y as i32,
-                        },
-                        WHITE,
-                    );
-                } else {
-                    atlas.draw_tile(
-                        map_tile,
-                        &Position {
-                            x: x as i32,
-                            y: y as i32,
-                        },
-                        GRAY,
-                    );
-                }
+    cache.update(
+        map,
+        revealed_tiles,
+        &viewshed.visible_tiles,
+        sprites,
+        masks,
+        camera.visible_bounds(),
+    );
+    set_camera(camera.camera2d());
+    cache.draw(map.len(), map.first().map_or(0, Vec::len));
+}
+
+/// Render the in-game entities the player can see ordered by their `SpriteLayer`, the player on top.
+#[system]
+#[read_component(Position)]
+#[read_component(Sprite)]
+#[read_component(Tint)]
+#[read_component(SpriteLayer)]
+#[read_component(Viewshed)]
+#[read_component(IsPlayer)]
+fn draw_entities(world: &SubWorld, #[resource] sprites: &mut SpriteManager) {
+    let mut players = <(&Position, &Sprite, &Viewshed)>::query().filter(component::<IsPlayer>());
+    let mut entities = <(&Position, &Sprite, Option<&Tint>, Option<&SpriteLayer>)>::query()
+        .filter(!component::<IsPlayer>());
+    for (player_pos, player_sprite, viewshed) in players.iter(world) {
+        let mut visible: Vec<_> = entities
+            .iter(world)
+            .filter(|(pos, ..)| viewshed.visible_tiles.contains(pos))
+            .collect();
+        visible.sort_by_key(|(.., layer)| layer.copied().unwrap_or(SpriteLayer::Items));
+        for (pos, sprite, tint, _) in visible {
+            sprites.draw_entity(
+                sprite,
+                pos,
+                tint.map_or(WHITE, |entity_tint| Color(entity_tint.color)),
+            );
+        }
+        sprites.draw_entity(player_sprite, player_pos, WHITE);
+    }
+}
+
+/// Preview the planned click-to-travel path.
+#[system]
+fn draw_travel_path(#[resource] travel: &Travel) {
+    for step in &travel.path {
+        draw_rectangle(
+            step.x as f32 + 0.35,
+            step.y as f32 + 0.35,
+            0.3,
+            0.3,
+            Color([200, 180, 60, 180]),
+        );
+    }
+}
+
+/// Render the targeting overlay and the mouse cursor. (in camera space)
+fn draw_cursor(run_state: &RunState, mouse_position: Vec2) {
+    if let RunState::ShowTargeting(targeting) = run_state {
+        targeting.draw();
+    }
+    draw_circle(
+        mouse_position.x(),
+        mouse_position.y(),
+        0.1,
+        Color([100, 75, 120, 255]),
+    );
+}
+
+/// Render the status, the log and the open menu in screen space. (after `set_default_camera()`)
+fn draw_screen(
+    sim: &Simulation,
+    hints: &[String],
+    run_state: &RunState,
+    inventory_menu: &InventoryMenu,
+) {
+    let status = <(&Purse, &CombatStats)>::query()
+        .filter(component::<IsPlayer>())
+        .iter(&sim.world)
+        .next()
+        .map(|(purse, stats)| (*purse, *stats));
+    if let (Some(log), Some(dungeon), Some((purse, stats))) = (
+        sim.resources.get::<GameLog>(),
+        sim.resources.get::<Dungeon>(),
+        status,
+    ) {
+        draw_ui(&log, hints, &purse, &stats, dungeon.depth);
+    }
+    match run_state {
+        RunState::ShowInventory => inventory_menu.draw(&backpack(&sim.world, sim.player)),
+        RunState::ShowTargeting(_) => {
+            if let Some(keymap) = sim.resources.get::<Keymap>() {
+                Targeting::draw_hint(&keymap);
             }
         }
+        RunState::Running | RunState::Quit => {}
     }
-    atlas.draw_tile(tile, pos, WHITE);
 }
 
 /// Render the fixed screen ui. (after `set_default_camera()`)
-fn draw_ui() {
+fn draw_ui(log: &GameLog, hints: &[String], purse: &Purse, stats: &CombatStats, depth: usize) {
     let text_color: Color = Color([100, 100, 100, 150]);
-    draw_text(",aoe to move camera", 10.0, 0.0, 20.0, text_color);
-    draw_text("'. to zoom camera", 10.0, 30.0, 20.0, text_color);
+    for (i, hint) in hints.iter().enumerate() {
+        draw_text(hint, 10.0, i as f32 * 30.0, 20.0, text_color);
+    }
     draw_text(
-        "arrow keys to move the player",
+        "left click to travel",
         10.0,
-        60.0,
+        hints.len() as f32 * 30.0,
         20.0,
         text_color,
     );
-}
 
-/// Handle the keyboard. Try to move the player (handles collisions).
-#[system(for_each)]
-fn handle_keyboard(
-    current_pos: &mut Position,
-    viewshed: &mut Viewshed,
-    _: &IsPlayer,
-    #[resource] map: &Vec<Vec<Tile>>,
-) {
-    // Saves the current position in case the destination is not walkable.
-    let mut pos = *current_pos;
-    if is_key_pressed(KeyCode::Right) {
-        pos.x += 1;
-    }
-    if is_key_pressed(KeyCode::Left) {
-        pos.x -= 1;
-    }
-    if is_key_pressed(KeyCode::Down) {
-        pos.y -= 1;
-    }
-    if is_key_pressed(KeyCode::Up) {
-        pos.y += 1;
+    draw_text(
+        &format!(
+            "Depth: {}  HP: {}/{}  Gold: {}",
+            depth + 1,
+            stats.hp,
+            stats.max_hp,
+            purse.gold
+        ),
+        10.0,
+        screen_height() - 150.0,
+        20.0,
+        WHITE,
+    );
+    for (i, message) in log.latest(5).iter().enumerate() {
+        let y = (i as f32).mul_add(22.0, screen_height() - 120.0);
+        draw_text(message, 10.0, y, 20.0, WHITE);
     }
+}
 
-    // Resets the position if the destination is not walkable.
-    // Prints coords of out-of-bounds entities.
-    if let Some(row) = map.get(pos.x as usize) {
-        if let Some(tile) = row.get(pos.y as usize) {
-            if tile.is_walkable() {
-                current_pos.x = pos.x;
-                current_pos.y = pos.y;
-                viewshed.dirty = true;
+/// Get the backpack slot of the `item` carried by the `player`.
+fn backpack_slot(world: &World, player: Entity, item: Entity) -> Option<usize> {
+    backpack(world, player)
+        .iter()
+        .position(|(entity, _)| *entity == item)
+}
+
+/// Handle the input of the current `RunState` and return the next one,
+/// with the command for the game systems when the player decided on one.
+fn handle_run_state(
+    run_state: RunState,
+    world: &World,
+    resources: &Resources,
+    inventory_menu: &mut InventoryMenu,
+    player: Entity,
+    mouse_position: Vec2,
+    clicked: bool,
+) -> (RunState, Option<PlayerCommand>) {
+    match run_state {
+        RunState::Running => {
+            let Some(keymap) = resources.get::<Keymap>() else {
+                return (RunState::Running, None);
+            };
+            if keymap.pressed(Action::Quit) {
+                return (RunState::Quit, None);
+            }
+            if keymap.pressed(Action::Inventory) {
+                inventory_menu.reset();
+                return (RunState::ShowInventory, None);
+            }
+            (
+                RunState::Running,
+                read_command(&keymap, mouse_position, clicked),
+            )
+        }
+        RunState::ShowInventory => match inventory_menu.handle_input(&backpack(world, player)) {
+            InventoryAction::NoResponse => (RunState::ShowInventory, None),
+            InventoryAction::Close => (RunState::Running, None),
+            InventoryAction::Use(item) => use_or_target(world, player, item),
+            InventoryAction::Drop(item) => (
+                RunState::Running,
+                backpack_slot(world, player, item).map(|slot| PlayerCommand::DropItem { slot }),
+            ),
+        },
+        RunState::Quit => (RunState::Quit, None),
+        RunState::ShowTargeting(mut targeting) => {
+            let Some(keymap) = resources.get::<Keymap>() else {
+                return (RunState::ShowTargeting(targeting), None);
+            };
+            match targeting.handle_input(&keymap, Position::from(mouse_position), clicked) {
+                TargetingAction::NoResponse => (RunState::ShowTargeting(targeting), None),
+                TargetingAction::Cancel => (RunState::ShowInventory, None),
+                TargetingAction::Confirm(target) => (
+                    RunState::Running,
+                    backpack_slot(world, player, targeting.item).map(|slot| {
+                        PlayerCommand::UseItem {
+                            slot,
+                            target: Some(target),
+                        }
+                    }),
+                ),
             }
         }
     }
 }
 
-/// Handle the mouse. Print the click position.
-fn handle_mouse(left_mouse_pressed: bool, mouse_position: Vec2) -> bool {
-    if is_mouse_button_down(MouseButton::Left) {
-        if !left_mouse_pressed {
-            debug!(
-                "Mouse click at relative x:{} , y:{}",
-                mouse_position.x() as i32,
-                mouse_position.y() as i32
-            );
-        }
-        true
-    } else {
-        false
+/// Use the item right away, or start targeting if it is ranged.
+fn use_or_target(world: &World, player: Entity, item: Entity) -> (RunState, Option<PlayerCommand>) {
+    let ranged = world.entry_ref(item).ok().and_then(|entry| {
+        let range = entry.get_component::<Ranged>().ok()?.range;
+        let radius = entry
+            .get_component::<AreaOfEffect>()
+            .map_or(0, |aoe| aoe.radius);
+        Some((range, radius))
+    });
+    let view = world.entry_ref(player).ok().and_then(|entry| {
+        let pos = *entry.get_component::<Position>().ok()?;
+        let viewshed = entry.get_component::<Viewshed>().ok()?;
+        Some((pos, viewshed.visible_tiles.clone()))
+    });
+    if let (Some((range, radius)), Some((pos, visible_tiles))) = (ranged, view) {
+        let targeting = Targeting::new(item, range, radius, pos, &visible_tiles);
+        return (RunState::ShowTargeting(targeting), None);
     }
+    (
+        RunState::Running,
+        backpack_slot(world, player, item)
+            .map(|slot| PlayerCommand::UseItem { slot, target: None }),
+    )
 }
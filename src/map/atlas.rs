@@ -0,0 +1,313 @@
+use crate::map::tiles::{Position, Sprite, Tile};
+use crate::raws::raws;
+use macroquad::{
+    draw_rectangle, draw_text, draw_texture_ex, error, load_texture, measure_text, set_camera,
+    vec2, warn, Camera2D, Color, DrawTextureParams, Rect, Texture2D, Vec2, BLACK,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+/// Compiled in descriptor of the default `Tiles.png`, used when the file can't be loaded.
+const DEFAULT_DESCRIPTOR: &str = include_str!("../../assets/Tiles.atlas.json");
+
+/// Describes how the tileset image is split into sprites.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct AtlasDescriptor {
+    /// Path to the tileset image.
+    pub image: String,
+    pub tile_width: f32,
+    pub tile_height: f32,
+    /// Empty border around the whole image, in pixels.
+    #[serde(default)]
+    pub margin: f32,
+    /// Gap between two neighbouring tiles, in pixels.
+    #[serde(default)]
+    pub spacing: f32,
+    /// Cut off from every side of a tile, keeps the neighbouring tiles from bleeding in.
+    #[serde(default)]
+    pub padding: f32,
+    #[serde(default)]
+    pub flip_x: bool,
+    #[serde(default)]
+    pub flip_y: bool,
+    /// Rotation of every sprite, in degrees.
+    #[serde(default)]
+    pub rotation: f32,
+    /// Sprite names with their column and row on the image.
+    #[serde(default)]
+    pub names: HashMap<String, [u32; 2]>,
+}
+
+impl AtlasDescriptor {
+    /// Load the descriptor file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&contents).map_err(|e| e.to_string())
+    }
+
+    /// The compiled in descriptor of `Tiles.png`.
+    pub fn default_descriptor() -> Self {
+        serde_json::from_str(DEFAULT_DESCRIPTOR).expect("default atlas descriptor is valid")
+    }
+
+    /// Get the part of the image with the sprite at `column` and `row`.
+    fn source(&self, column: u32, row: u32) -> Rect {
+        let left = (self.tile_width + self.spacing).mul_add(column as f32, self.margin);
+        let top = (self.tile_height + self.spacing).mul_add(row as f32, self.margin);
+        let width = 2.0f32.mul_add(-self.padding, self.tile_width);
+        let height = 2.0f32.mul_add(-self.padding, self.tile_height);
+        let mut source = Rect {
+            x: left + self.padding,
+            y: top + self.padding,
+            w: width,
+            h: height,
+        };
+        // Drawing a source rect with negative size mirrors the sprite.
+        if self.flip_x {
+            source.x += width;
+            source.w = -width;
+        }
+        if self.flip_y {
+            source.y += height;
+            source.h = -height;
+        }
+        source
+    }
+}
+
+/// Where a sprite is: the index of its sheet and its column and row on the sheet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SpriteId {
+    sheet: usize,
+    column: u32,
+    row: u32,
+}
+
+/// How the tiles are rendered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Sprites from the atlas sheets.
+    #[default]
+    Tiles,
+    /// Colored text glyphs, no textures are needed.
+    Glyphs,
+}
+
+/// Glyphs of the autotiled tiles by their neighbour mask (`autotile::NORTH` ...) in the
+/// glyphs mode. The default font only has ASCII, so there are no box-drawing characters:
+/// lines follow the neighbours, corners are `o` and a tile with every neighbour or none stays `#`.
+/// Doors use `+`, they must not look like a corner of the wall they are set in.
+pub const AUTOTILE_GLYPHS: [char; 16] = [
+    '#', '|', '-', 'o', '|', '|', 'o', '|', '-', 'o', '-', '-', 'o', '|', '-', '#',
+];
+
+/// Multiply the channels of two colors, used to tint a color.
+fn modulate(Color(first): Color, Color(second): Color) -> Color {
+    let channel = |i: usize| (u16::from(first[i]) * u16::from(second[i]) / 255) as u8;
+    Color([channel(0), channel(1), channel(2), channel(3)])
+}
+
+/// Holds every tileset texture (terrain, creatures, items, ui icons) and finds the
+/// texture and rect of a sprite by its name.
+#[derive(Clone, Debug, Default)]
+pub struct SpriteManager {
+    mode: RenderMode,
+    sheets: Vec<(Texture2D, AtlasDescriptor)>,
+    sprites: HashMap<String, SpriteId>,
+    /// Autotile variants of the sprites, named `<sprite>_<mask>` in the descriptors.
+    variants: HashMap<String, [Option<SpriteId>; 16]>,
+    /// Glyphs waiting for `draw_glyphs()`, they are drawn in one pass with a flipped camera.
+    glyphs: Vec<(char, Position, Color)>,
+}
+
+impl SpriteManager {
+    /// Load the descriptors and their textures. Sprite names of the later sheets
+    /// override the earlier ones. Falls back to the default descriptor if none can be loaded.
+    /// Nothing is loaded in the `glyphs` mode.
+    pub async fn load(paths: &[String], mode: RenderMode) -> Self {
+        if mode == RenderMode::Glyphs {
+            return Self {
+                mode,
+                ..Self::default()
+            };
+        }
+        let mut descriptors: Vec<AtlasDescriptor> = paths
+            .iter()
+            .filter_map(|path| match AtlasDescriptor::load(path) {
+                Ok(descriptor) => Some(descriptor),
+                Err(e) => {
+                    error!("Unable to load atlas descriptor at {}! Error: {}", path, e);
+                    None
+                }
+            })
+            .collect();
+        if descriptors.is_empty() {
+            error!("No atlas descriptors loaded! Loading default descriptor.");
+            descriptors.push(AtlasDescriptor::default_descriptor());
+        }
+
+        let mut manager = Self::default();
+        // Sheets may share an image, load each texture only once.
+        let mut textures: HashMap<String, Texture2D> = HashMap::new();
+        for descriptor in descriptors {
+            let texture = if let Some(texture) = textures.get(&descriptor.image) {
+                *texture
+            } else {
+                let texture = load_texture(&descriptor.image).await;
+                textures.insert(descriptor.image.clone(), texture);
+                texture
+            };
+            manager.add_sheet(texture, descriptor);
+        }
+        manager
+    }
+
+    /// Add a sheet and register its sprite names.
+    pub fn add_sheet(&mut self, texture: Texture2D, descriptor: AtlasDescriptor) {
+        let sheet = self.sheets.len();
+        for (name, &[column, row]) in &descriptor.names {
+            let id = SpriteId { sheet, column, row };
+            if let Some((base, suffix)) = name.rsplit_once('_') {
+                if let Ok(mask @ 0..=15) = suffix.parse::<usize>() {
+                    self.variants.entry(base.to_string()).or_default()[mask] = Some(id);
+                }
+            }
+            if self.sprites.insert(name.clone(), id).is_some() {
+                warn!(
+                    "Sprite {} is defined by several sheets, using {}",
+                    name, descriptor.image
+                );
+            }
+        }
+        self.sheets.push((texture, descriptor));
+    }
+
+    /// Draw the sprite called `name` to the given position.
+    /// Unknown names are drawn with the first sprite of the first sheet.
+    pub fn draw_sprite(&self, name: &str, pos: &Position, color: Color) {
+        let id = self.sprites.get(name).copied().unwrap_or(SpriteId {
+            sheet: 0,
+            column: 0,
+            row: 0,
+        });
+        self.draw_sprite_id(id, pos, color);
+    }
+
+    fn draw_sprite_id(&self, id: SpriteId, pos: &Position, color: Color) {
+        let Some((texture, descriptor)) = self.sheets.get(id.sheet) else {
+            return;
+        };
+        let params = DrawTextureParams {
+            dest_size: Some(Vec2::one()),
+            source: Some(descriptor.source(id.column, id.row)),
+            rotation: descriptor.rotation.to_radians(),
+        };
+        draw_texture_ex(*texture, pos.x as f32, pos.y as f32, color, params);
+    }
+
+    /// Draw the `sprite` of an entity tinted with `color` to the given position.
+    /// In the glyphs mode the tile under it is blanked and the glyph is only queued, see `draw_glyphs()`.
+    pub fn draw_entity(&mut self, sprite: &Sprite, pos: &Position, color: Color) {
+        match self.mode {
+            RenderMode::Tiles => self.draw_sprite(&sprite.name, pos, color),
+            RenderMode::Glyphs => {
+                // Hide the glyph of the map tile, two glyphs on one tile can't be read.
+                draw_rectangle(pos.x as f32, pos.y as f32, 1.0, 1.0, BLACK);
+                // The glyph has its own color, the tint is for the sprite.
+                self.glyphs
+                    .push((sprite.glyph, *pos, Color(sprite.glyph_color)));
+            }
+        }
+    }
+
+    /// Draw provided tile kind (e.g. `Tile::GRASS`) to the given position,
+    /// `color` is applied on top of the tile tint. Uses the variant of the sprite for
+    /// the autotile `mask` when the sheets have one. In the glyphs mode the tiles named after their autotile group
+    /// (walls, but not the doors set in them) get the glyph of the mask.
+    pub fn draw_autotile(&mut self, tile: &Tile, pos: &Position, color: Color, mask: Option<u8>) {
+        let definition = raws().tile(*tile);
+        match self.mode {
+            RenderMode::Tiles => {
+                let tint = modulate(color, tile.tint());
+                let variant = mask.and_then(|bits| {
+                    self.variants
+                        .get(&definition.sprite)
+                        .and_then(|variants| variants[usize::from(bits & 15)])
+                });
+                match variant {
+                    Some(id) => self.draw_sprite_id(id, pos, tint),
+                    None => self.draw_sprite(&definition.sprite, pos, tint),
+                }
+            }
+            RenderMode::Glyphs => {
+                let glyph_color = Color(definition.glyph_color.unwrap_or(definition.tint));
+                let glyph = match mask {
+                    Some(bits) if definition.autotile.as_ref() == Some(&definition.sprite) => {
+                        AUTOTILE_GLYPHS[usize::from(bits & 15)]
+                    }
+                    _ => definition.glyph,
+                };
+                self.glyphs
+                    .push((glyph, *pos, modulate(color, glyph_color)));
+            }
+        }
+    }
+
+    /// Draw the queued glyphs with the `camera`, which stays set afterwards. The world is y-up
+    /// while text is drawn y-down, so the glyphs are drawn mirrored with a mirrored copy of the camera.
+    pub fn draw_glyphs(&mut self, camera: Camera2D) {
+        if self.glyphs.is_empty() {
+            return;
+        }
+        set_camera(Camera2D {
+            target: vec2(camera.target.x(), -camera.target.y()),
+            zoom: vec2(camera.zoom.x(), -camera.zoom.y()),
+            ..camera
+        });
+        for (glyph, pos, color) in self.glyphs.drain(..) {
+            let text = glyph.to_string();
+            let (width, _) = measure_text(&text, 1.0);
+            draw_text(
+                &text,
+                pos.x as f32 + (1.0 - width) / 2.0,
+                -pos.y as f32 - 1.0,
+                1.0,
+                color,
+            );
+        }
+        set_camera(camera);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_sprites_are_inside_the_image() {
+        let descriptor = AtlasDescriptor::default_descriptor();
+        let png = fs::read(&descriptor.image).expect("the atlas image");
+        // The size is at the start of the IHDR chunk, right after the PNG signature.
+        let size = |at: usize| {
+            png[at..at + 4]
+                .iter()
+                .fold(0, |size, byte| size << 8 | u32::from(*byte)) as f32
+        };
+        assert_eq!(&png[12..16], b"IHDR");
+        let (width, height) = (size(16), size(20));
+        for (name, [column, row]) in &descriptor.names {
+            let source = descriptor.source(*column, *row);
+            assert!(
+                source.x >= 0.0
+                    && source.y >= 0.0
+                    && source.x + source.w <= width
+                    && source.y + source.h <= height,
+                "the {} sprite is outside of the {}x{} image",
+                name,
+                width,
+                height
+            );
+        }
+    }
+}
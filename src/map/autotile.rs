@@ -0,0 +1,148 @@
+use crate::map::doors::TileChanges;
+use crate::map::tiles::{Position, Tile};
+use crate::raws::raws;
+use legion::system;
+
+/// Bits of the neighbour mask, a bit is set when the neighbour is in the same autotile group.
+pub const NORTH: u8 = 1;
+pub const EAST: u8 = 2;
+pub const SOUTH: u8 = 4;
+pub const WEST: u8 = 8;
+
+/// Offsets of the neighbours with their bits. (y is up)
+const NEIGHBOURS: [(i32, i32, u8); 4] =
+    [(0, 1, NORTH), (1, 0, EAST), (0, -1, SOUTH), (-1, 0, WEST)];
+
+/// Neighbour masks of the autotiled map tiles, computed once per map.
+/// Tiles without an autotile group have mask 0.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AutotileMasks {
+    masks: Vec<Vec<u8>>,
+}
+
+/// Get the autotile group of the tile at `x`, `y`, `None` outside of the map.
+fn group_at(map: &[Vec<Tile>], x: i32, y: i32) -> Option<&'static str> {
+    if x < 0 || y < 0 {
+        return None;
+    }
+    let tile = map.get(x as usize)?.get(y as usize)?;
+    raws().tile(*tile).autotile.as_deref()
+}
+
+/// Compute the mask of the tile at `x`, `y` from its 4 neighbours.
+fn mask_at(map: &[Vec<Tile>], x: i32, y: i32) -> u8 {
+    let Some(group) = group_at(map, x, y) else {
+        return 0;
+    };
+    NEIGHBOURS
+        .iter()
+        .filter(|(dx, dy, _)| group_at(map, x + dx, y + dy) == Some(group))
+        .fold(0, |mask, (_, _, bit)| mask | bit)
+}
+
+impl AutotileMasks {
+    /// Compute the masks of the whole map.
+    pub fn new(map: &[Vec<Tile>]) -> Self {
+        let masks = map
+            .iter()
+            .enumerate()
+            .map(|(x, row)| {
+                (0..row.len())
+                    .map(|y| mask_at(map, x as i32, y as i32))
+                    .collect()
+            })
+            .collect();
+        Self { masks }
+    }
+
+    /// Get the mask of the tile at `pos`.
+    pub fn get(&self, pos: Position) -> u8 {
+        self.masks
+            .get(pos.x as usize)
+            .and_then(|row| row.get(pos.y as usize))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Recompute the masks of the changed tiles and their neighbours.
+    pub fn update(&mut self, map: &[Vec<Tile>], changed: &[Position]) {
+        for pos in changed {
+            for (dx, dy) in [(0, 0), (0, 1), (1, 0), (0, -1), (-1, 0)] {
+                let (x, y) = (pos.x + dx, pos.y + dy);
+                if x < 0 || y < 0 {
+                    continue;
+                }
+                if let Some(mask) = self
+                    .masks
+                    .get_mut(x as usize)
+                    .and_then(|row| row.get_mut(y as usize))
+                {
+                    *mask = mask_at(map, x, y);
+                }
+            }
+        }
+    }
+}
+
+/// Keep the autotile masks in sync with the tiles changed this turn.
+#[system]
+pub fn update_autotile(
+    #[resource] map: &Vec<Vec<Tile>>,
+    #[resource] changes: &TileChanges,
+    #[resource] masks: &mut AutotileMasks,
+) {
+    if !changes.tiles.is_empty() {
+        masks.update(map, &changes.tiles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::atlas::{AtlasDescriptor, AUTOTILE_GLYPHS};
+    use crate::map::generators::rooms_map;
+    use std::collections::HashSet;
+
+    #[test]
+    fn room_walls_have_a_sprite_per_mask() {
+        fastrand::seed(42);
+        let map = rooms_map(50, 50, 30);
+        let masks = AutotileMasks::new(&map.tiles);
+        let wall_masks: HashSet<u8> = map
+            .tiles
+            .iter()
+            .enumerate()
+            .flat_map(|(x, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, tile)| **tile == Tile::WALL)
+                    .map(move |(y, _)| Position {
+                        x: x as i32,
+                        y: y as i32,
+                    })
+            })
+            .map(|pos| masks.get(pos))
+            .collect();
+        // The walls of a room and the rock around it don't all look the same.
+        assert!(wall_masks.len() > 1, "wall masks: {:?}", wall_masks);
+
+        let descriptor = AtlasDescriptor::default_descriptor();
+        for mask in wall_masks {
+            assert!(
+                descriptor.names.contains_key(&format!("wall_{mask}")),
+                "no sprite for the wall mask {}",
+                mask
+            );
+        }
+
+        // In the glyphs mode a door must not look like a piece of the wall around it.
+        let doors = raws().raws.tiles.iter().filter(|tile| tile.door.is_some());
+        for door in doors.map(|tile| tile.glyph) {
+            assert!(
+                !AUTOTILE_GLYPHS.contains(&door),
+                "the door glyph {} is also a wall glyph",
+                door
+            );
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::map::tiles::{DoorState, Position, Tile};
+use fastrand::Rng;
+
+/// Map tiles changed this turn, the entities that can see them need to update their `Viewshed`.
+#[derive(Clone, Debug, Default)]
+pub struct TileChanges {
+    pub tiles: Vec<Position>,
+}
+
+impl TileChanges {
+    pub fn add(&mut self, pos: Position) {
+        self.tiles.push(pos);
+    }
+}
+
+/// What happened when something bumped into a door.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoorBump {
+    Opened,
+    /// The lock was picked, the door is closed now.
+    PickedLock,
+    StillLocked,
+}
+
+/// Try to open the door at `pos`, locked doors get picked with some luck.
+/// Returns `None` if there is no closed door.
+pub fn bump_door(
+    map: &mut Vec<Vec<Tile>>,
+    pos: Position,
+    changes: &mut TileChanges,
+) -> Option<DoorBump> {
+    let tile = map.get_mut(pos.x as usize)?.get_mut(pos.y as usize)?;
+    // Only doors can be bumped open, every other tile is left alone.
+    let state = tile.door()?;
+    let bump = match state {
+        DoorState::Closed => {
+            *tile = Tile::OPEN_DOOR;
+            changes.add(pos);
+            DoorBump::Opened
+        }
+        DoorState::Locked => {
+            if Rng::new().u8(..3) == 0 {
+                *tile = Tile::CLOSED_DOOR;
+                changes.add(pos);
+                DoorBump::PickedLock
+            } else {
+                DoorBump::StillLocked
+            }
+        }
+        DoorState::Open => return None,
+    };
+    Some(bump)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::tests::tiles_from_rows;
+
+    const fn pos(x: i32, y: i32) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn bump_opens_closed_doors_only() {
+        let mut map = tiles_from_rows(&[".+'#"]);
+        let mut changes = TileChanges::default();
+        assert_eq!(
+            bump_door(&mut map, pos(1, 0), &mut changes),
+            Some(DoorBump::Opened)
+        );
+        assert_eq!(map[1][0], Tile::OPEN_DOOR);
+        assert_eq!(changes.tiles, vec![pos(1, 0)]);
+
+        let before = map.clone();
+        for bumped in [pos(0, 0), pos(1, 0), pos(2, 0), pos(3, 0), pos(9, 0)] {
+            assert_eq!(bump_door(&mut map, bumped, &mut changes), None);
+        }
+        assert_eq!(map, before);
+        assert_eq!(changes.tiles, vec![pos(1, 0)]);
+    }
+
+    #[test]
+    fn bump_picks_locks_with_some_luck() {
+        fastrand::seed(2);
+        let mut map = tiles_from_rows(&["L"]);
+        let mut changes = TileChanges::default();
+        let mut tries = 0;
+        loop {
+            tries += 1;
+            assert!(tries < 100, "the lock was never picked");
+            match bump_door(&mut map, pos(0, 0), &mut changes) {
+                Some(DoorBump::StillLocked) => {
+                    assert_eq!(map[0][0], Tile::LOCKED_DOOR);
+                    assert!(changes.tiles.is_empty());
+                }
+                Some(DoorBump::PickedLock) => break,
+                other => panic!("unexpected bump {:?}", other),
+            }
+        }
+        assert!(
+            tries > 1,
+            "the lock gave way on the first try with this seed"
+        );
+        assert_eq!(map[0][0], Tile::CLOSED_DOOR);
+        assert_eq!(changes.tiles, vec![pos(0, 0)]);
+
+        assert_eq!(
+            bump_door(&mut map, pos(0, 0), &mut changes),
+            Some(DoorBump::Opened)
+        );
+    }
+}
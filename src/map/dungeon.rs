@@ -0,0 +1,159 @@
+use crate::characters::player::{IsPlayer, Travel, Viewshed};
+use crate::items::InBackpack;
+use crate::map::autotile::AutotileMasks;
+use crate::map::doors::TileChanges;
+use crate::map::generators::{perlin_noise_map, rooms_map, Map};
+use crate::map::tiles::{Position, Tile};
+use crate::spawner::populate_level;
+use crate::utils::gamelog::GameLog;
+use crate::utils::settings::Settings;
+use legion::systems::CommandBuffer;
+use legion::{any, component, Entity, Resources, World};
+
+/// A level with all the entities left on it.
+pub struct Level {
+    pub map: Map,
+    pub world: World,
+}
+
+/// Every visited level of the dungeon. The level being played lives in the ECS
+/// world and resources instead, its slot is empty.
+#[derive(Default)]
+pub struct Dungeon {
+    pub depth: usize,
+    levels: Vec<Option<Level>>,
+}
+
+impl Dungeon {
+    /// Restore the dungeon of a saved game, `levels` are indexed by depth.
+    pub const fn restore(depth: usize, levels: Vec<Option<Level>>) -> Self {
+        Self { depth, levels }
+    }
+
+    /// The stored levels by depth, `None` for the current one and the levels never visited.
+    pub fn levels(&self) -> &[Option<Level>] {
+        &self.levels
+    }
+
+    fn store(&mut self, depth: usize, level: Level) {
+        if self.levels.len() <= depth {
+            self.levels.resize_with(depth + 1, || None);
+        }
+        self.levels[depth] = Some(level);
+    }
+
+    fn take(&mut self, depth: usize) -> Option<Level> {
+        self.levels.get_mut(depth).and_then(Option::take)
+    }
+}
+
+/// Generate a new level for the given depth and populate it from the spawn table.
+pub fn build_level(settings: &Settings, depth: usize) -> Level {
+    // Tile is the key of a tile kind in the raws, like wall, grass, pengu.
+    // `rooms_map()` is a generator for the level. (There are
+    // different types of generators)
+    println!(
+        "generating the map {}:{} size for depth {}",
+        settings.width, settings.height, depth
+    );
+    let mut map = match settings.generator.as_str() {
+        "rooms" => rooms_map(settings.width, settings.height, settings.gen_param),
+        _ => perlin_noise_map(settings.width, settings.height, 0.25f64),
+    };
+    map.place_stairs(depth > 0);
+
+    // Monsters and items get stronger and more numerous the deeper the level is.
+    let mut world = World::default();
+    let mut cmd = CommandBuffer::new(&world);
+    populate_level(&mut cmd, &map, depth);
+    cmd.flush(&mut world);
+
+    Level { map, world }
+}
+
+/// Make the level the one being played: its entities go to the `world`, the map to the `resources`.
+pub fn enter_level(level: Level, world: &mut World, resources: &mut Resources) {
+    let mut level_world = level.world;
+    level_world.move_from(world, &any());
+    *world = level_world;
+    resources.insert(AutotileMasks::new(&level.map.tiles));
+    resources.insert(level.map.tiles);
+    resources.insert(level.map.revealed_tiles);
+}
+
+/// Take the stairs at the player's position. Stores the current level in the `Dungeon`
+/// and moves the player with their backpack to the level above or below.
+/// Returns the new position of the player, `None` if there are no stairs.
+pub fn take_stairs(
+    world: &mut World,
+    resources: &mut Resources,
+    settings: &Settings,
+    player: Entity,
+) -> Option<Position> {
+    let pos = *world.entry(player)?.get_component::<Position>().ok()?;
+    let tile = *resources
+        .get::<Vec<Vec<Tile>>>()?
+        .get(pos.x as usize)?
+        .get(pos.y as usize)?;
+    let mut dungeon = resources.remove::<Dungeon>()?;
+    let (new_depth, arrival_tile) = if tile == Tile::STAIRS_DOWN {
+        (dungeon.depth + 1, Tile::STAIRS_UP)
+    } else if tile == Tile::STAIRS_UP && dungeon.depth > 0 {
+        (dungeon.depth - 1, Tile::STAIRS_DOWN)
+    } else {
+        resources.insert(dungeon);
+        if let Some(mut log) = resources.get_mut::<GameLog>() {
+            log.add("There are no stairs here.".to_string());
+        }
+        return None;
+    };
+
+    // The player and everything they carry leave the level.
+    let mut travellers = World::default();
+    travellers.move_from(
+        world,
+        &(component::<IsPlayer>() | component::<InBackpack>()),
+    );
+    let tiles = resources.remove::<Vec<Vec<Tile>>>().unwrap_or_default();
+    let revealed_tiles = resources.remove::<Vec<Vec<bool>>>().unwrap_or_default();
+    let left_world = std::mem::take(world);
+    dungeon.store(
+        dungeon.depth,
+        Level {
+            map: Map::from_parts(tiles, revealed_tiles),
+            world: left_world,
+        },
+    );
+
+    let level = dungeon
+        .take(new_depth)
+        .unwrap_or_else(|| build_level(settings, new_depth));
+    let arrival = level
+        .map
+        .find_tile(arrival_tile)
+        .unwrap_or(level.map.entrance);
+    *world = travellers;
+    enter_level(level, world, resources);
+    dungeon.depth = new_depth;
+    resources.insert(dungeon);
+
+    if let Some(mut entry) = world.entry(player) {
+        if let Ok(player_pos) = entry.get_component_mut::<Position>() {
+            *player_pos = arrival;
+        }
+        if let Ok(viewshed) = entry.get_component_mut::<Viewshed>() {
+            viewshed.dirty = true;
+            viewshed.visible_tiles.clear();
+        }
+    }
+    if let Some(mut travel) = resources.get_mut::<Travel>() {
+        travel.cancel();
+    }
+    if let Some(mut changes) = resources.get_mut::<TileChanges>() {
+        changes.tiles.clear();
+    }
+    if let Some(mut log) = resources.get_mut::<GameLog>() {
+        log.add(format!("You arrive at depth {}.", new_depth + 1));
+    }
+    Some(arrival)
+}
@@ -1,4 +1,4 @@
-use crate::map::tiles::Tile;
+use crate::map::tiles::{Position, Tile};
 use crate::map::{Point, Rect};
 use fastrand::Rng;
 use noise::*;
@@ -8,6 +8,8 @@ pub struct Map {
     pub tiles: Vec<Vec<Tile>>,
     pub revealed_tiles: Vec<Vec<bool>>,
     pub rooms: Option<Vec<Rect>>,
+    /// Where the player arrives on this level.
+    pub entrance: Position,
     width: usize,
     height: usize,
 }
@@ -21,11 +23,69 @@ impl Map {
             tiles,
             revealed_tiles,
             rooms: None,
+            entrance: Position { x: 1, y: 1 },
             width,
             height,
         }
     }
 
+    /// Puts the map back together from the tiles that were used as ECS resources.
+    pub fn from_parts(tiles: Vec<Vec<Tile>>, revealed_tiles: Vec<Vec<bool>>) -> Self {
+        let width = tiles.len();
+        let height = tiles.first().map_or(0, Vec::len);
+        Self {
+            tiles,
+            revealed_tiles,
+            rooms: None,
+            entrance: Position { x: 1, y: 1 },
+            width,
+            height,
+        }
+    }
+
+    /// Find the first tile of the given kind.
+    pub fn find_tile(&self, kind: Tile) -> Option<Position> {
+        for (x, row) in self.tiles.iter().enumerate() {
+            if let Some(y) = row.iter().position(|tile| *tile == kind) {
+                return Some(Position {
+                    x: x as i32,
+                    y: y as i32,
+                });
+            }
+        }
+        None
+    }
+
+    /// Sets the entrance and places the stairs down (and up, if there is a level above).
+    /// Room maps go from the last room to the first one, other maps use random tiles.
+    pub fn place_stairs(&mut self, with_stairs_up: bool) {
+        let rng = Rng::new();
+        let rooms_ends = self.rooms.as_ref().and_then(|rooms| {
+            let first = rooms.first()?.center();
+            let last = rooms.last()?.center();
+            Some((Position::from(last), Position::from(first)))
+        });
+        let (start, end) = rooms_ends.map_or_else(
+            || {
+                (
+                    self.random_walkable_position(&rng),
+                    self.random_walkable_position(&rng),
+                )
+            },
+            |(last, first)| (Some(last), Some(first)),
+        );
+
+        if let Some(pos) = start {
+            self.entrance = pos;
+            if with_stairs_up {
+                self.tiles[pos.x as usize][pos.y as usize] = Tile::STAIRS_UP;
+            }
+        }
+        if let Some(pos) = end.filter(|pos| *pos != self.entrance) {
+            self.tiles[pos.x as usize][pos.y as usize] = Tile::STAIRS_DOWN;
+        }
+    }
+
     pub fn set_rooms(&mut self, rooms: Option<Vec<Rect>>) {
         self.rooms = rooms;
         self.apply_rooms();
@@ -35,26 +95,52 @@ impl Map {
     pub fn make_borders(&mut self) {
         // Make the boundaries walls
         for x in 0..self.width {
-            self.tiles[x as usize][0] = Tile::Wall;
-            self.tiles[x as usize][(self.height - 1) as usize] = Tile::Wall;
+            self.tiles[x as usize][0] = Tile::WALL;
+            self.tiles[x as usize][(self.height - 1) as usize] = Tile::WALL;
         }
         for y in 0..self.height {
-            self.tiles[0][y as usize] = Tile::Wall;
-            self.tiles[(self.width - 1) as usize][y as usize] = Tile::Wall;
+            self.tiles[0][y as usize] = Tile::WALL;
+            self.tiles[(self.width - 1) as usize][y as usize] = Tile::WALL;
+        }
+    }
+
+    /// Get a random walkable position, `None` if none was found in a reasonable amount of tries.
+    pub fn random_walkable_position(&self, rng: &Rng) -> Option<Position> {
+        const TRIES: usize = 1000;
+        for _ in 0..TRIES {
+            let x = rng.usize(..self.width);
+            let y = rng.usize(..self.height);
+            if self.tiles[x][y].is_walkable() {
+                return Some(Position {
+                    x: x as i32,
+                    y: y as i32,
+                });
+            }
+        }
+        None
+    }
+
+    /// Replaces random walkable tiles with chests.
+    pub fn place_chests(&mut self, count: usize) {
+        let rng = Rng::new();
+        for _ in 0..count {
+            if let Some(pos) = self.random_walkable_position(&rng) {
+                self.tiles[pos.x as usize][pos.y as usize] = Tile::CHEST;
+            }
         }
     }
 
     fn apply_vertical_corridor(&mut self, starting_point: &Point, len: i32) {
         let (x, y) = starting_point.as_tuple();
         for target_y in min(y, y + len)..=max(y, y + len) {
-            self.tiles[x as usize][target_y as usize] = Tile::Grass;
+            self.tiles[x as usize][target_y as usize] = Tile::GRASS;
         }
     }
 
     fn apply_horizontal_corridor(&mut self, starting_point: &Point, len: i32) {
         let (x, y) = starting_point.as_tuple();
         for target_x in min(x, x + len)..=max(x, x + len) {
-            self.tiles[target_x as usize][y as usize] = Tile::Grass;
+            self.tiles[target_x as usize][y as usize] = Tile::GRASS;
         }
     }
 
@@ -63,18 +149,59 @@ impl Map {
             for room in rooms.iter() {
                 let walls = room.get_walls_positions();
                 for (x, y) in walls {
-                    self.tiles[x][y] = Tile::Wall;
+                    self.tiles[x][y] = Tile::WALL;
                 }
 
                 let floors = room.get_floors_positions();
                 for (x, y) in floors {
-                    self.tiles[x][y] = Tile::Grass;
+                    self.tiles[x][y] = Tile::GRASS;
                 }
             }
             self.connect_rooms();
+            self.place_doors();
         }
     }
 
+    /// Put doors where the corridors cut through the room walls.
+    /// Only gaps with walls on both sides get a door, so corridors running along a wall stay open.
+    fn place_doors(&mut self) {
+        let rng = Rng::new();
+        let mut doors: Vec<(usize, usize)> = Vec::new();
+        if let Some(rooms) = &self.rooms {
+            for room in rooms.iter() {
+                for (x, y) in room.get_walls_positions() {
+                    let on_border = x as i32 == room.top_left.x - 1
+                        || x as i32 == room.down_right.x
+                        || y as i32 == room.top_left.y - 1
+                        || y as i32 == room.down_right.y;
+                    if on_border && self.tiles[x][y] == Tile::GRASS && self.is_doorway(x, y) {
+                        doors.push((x, y));
+                    }
+                }
+            }
+        }
+        for (x, y) in doors {
+            self.tiles[x][y] = if rng.u8(..5) == 0 {
+                Tile::LOCKED_DOOR
+            } else {
+                Tile::CLOSED_DOOR
+            };
+        }
+    }
+
+    /// Check if the tile is a gap between two walls.
+    fn is_doorway(&self, x: usize, y: usize) -> bool {
+        let is_wall = |x: usize, y: usize| {
+            self.tiles
+                .get(x)
+                .and_then(|row| row.get(y))
+                .is_some_and(|tile| *tile == Tile::WALL)
+        };
+        let horizontal = x > 0 && is_wall(x - 1, y) && is_wall(x + 1, y);
+        let vertical = y > 0 && is_wall(x, y - 1) && is_wall(x, y + 1);
+        horizontal || vertical
+    }
+
     /// Connect a pair of rooms with L shaped corridor.
     fn connect_rooms(&mut self) {
         let mut corridors: Vec<(Point, Point)> = Vec::new();
@@ -97,7 +224,7 @@ pub fn rooms_map(width: usize, height: usize, max_rooms: i32) -> Map {
     const MIN_SIZE: usize = 3;
     const MAX_SIZE: usize = 15;
 
-    let mut map = Map::new(Tile::Wall, width, height);
+    let mut map = Map::new(Tile::WALL, width, height);
 
     let mut rooms: Vec<Rect> = Vec::new();
     let rng = Rng::new();
@@ -127,7 +254,10 @@ pub fn rooms_map(width: usize, height: usize, max_rooms: i32) -> Map {
         }
     }
 
+    // One chest for every two rooms that fit, most of the `max_rooms` attempts overlap.
+    let chests = rooms.len() / 2;
     map.set_rooms(Some(rooms));
+    map.place_chests(chests);
 
     /*
     let other_rooms: Vec<&Rect> = repeat_with(|| rooms.get(rng.usize(..rooms.len())).unwrap())
@@ -142,11 +272,12 @@ pub fn rooms_map(width: usize, height: usize, max_rooms: i32) -> Map {
 }
 
 pub fn perlin_noise_map(width: usize, height: usize, freq: f64) -> Map {
-    let mut map = Map::new(Tile::Grass, width, height);
+    let mut map = Map::new(Tile::GRASS, width, height);
     // Now we'll randomly splat a bunch of walls. It won't be pretty, but it's a decent illustration.
     // First, obtain the thread-local RNG:
     let rng = Rng::new();
-    let perlin = Perlin::new().set_seed(0u32);
+    // Every level gets its own terrain, a seeded run still gets the same levels.
+    let perlin = Perlin::new().set_seed(rng.u32(..));
     let perlin_freq = ScalePoint::new(&perlin).set_scale(freq);
     let perlin_norm = ScaleBias::new(&perlin_freq)
         .set_bias(1f64)
@@ -156,7 +287,7 @@ pub fn perlin_noise_map(width: usize, height: usize, freq: f64) -> Map {
         for y in 0..height {
             let value = perlin_norm.get([x as f64, y as f64]);
             if value > 0.8f64 {
-                map.tiles[x][y] = Tile::Debug;
+                map.tiles[x][y] = Tile::DEBUG;
             } else if value > 1.0f64 {
                 println!("thats not supposed to happen");
             }
@@ -164,12 +295,13 @@ pub fn perlin_noise_map(width: usize, height: usize, freq: f64) -> Map {
     }
 
     map.make_borders();
+    map.place_chests(width * height / 500);
     map
 }
 
 /// Generates a map. Randomly placed walls.
 pub fn _random_map(width: usize, height: usize, num_walls: i32) -> Map {
-    let mut map = Map::new(Tile::Grass, width, height);
+    let mut map = Map::new(Tile::GRASS, width, height);
     // Now we'll randomly splat a bunch of walls. It won't be pretty, but it's a decent illustration.
     // First, obtain the thread-local RNG:
     let rng = Rng::new();
@@ -177,9 +309,61 @@ pub fn _random_map(width: usize, height: usize, num_walls: i32) -> Map {
     for _i in 0..num_walls {
         let x = rng.usize(..(width - 1) as usize);
         let y = rng.usize(..(height - 1) as usize);
-        map.tiles[x][y] = Tile::Wall;
+        map.tiles[x][y] = Tile::WALL;
     }
 
     map.make_borders();
     map
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::tests::tiles_from_rows;
+
+    /// A room with a corridor coming in through the west wall
+    /// and another one cutting a wide gap in the north wall.
+    #[rustfmt::skip]
+    const ROOM: [&str; 6] = [
+        "###..##",
+        "###..##",
+        "##...##",
+        ".....##",
+        "##...##",
+        "#######",
+    ];
+
+    fn room_map() -> Map {
+        let mut map = Map::from_parts(tiles_from_rows(&ROOM), Vec::new());
+        map.rooms = Some(vec![Rect::new(Point { x: 2, y: 2 }, 3, 3)]);
+        map
+    }
+
+    #[test]
+    fn doorways_are_gaps_between_two_walls() {
+        let map = room_map();
+        assert!(map.is_doorway(1, 3));
+        assert!(!map.is_doorway(3, 1));
+        assert!(!map.is_doorway(4, 1));
+        assert!(!map.is_doorway(3, 3));
+    }
+
+    #[test]
+    fn doors_are_placed_in_doorways_only() {
+        let mut map = room_map();
+        map.place_doors();
+        let mut doors = Vec::new();
+        for (x, row) in map.tiles.iter().enumerate() {
+            for (y, tile) in row.iter().enumerate() {
+                if tile.door().is_some() {
+                    doors.push((x, y));
+                }
+            }
+        }
+        // The corridor outside the room and the wide gap stay open.
+        assert_eq!(doors, vec![(1, 3)]);
+        assert_eq!(map.tiles[0][3], Tile::GRASS);
+        assert_eq!(map.tiles[3][1], Tile::GRASS);
+        assert_eq!(map.tiles[4][1], Tile::GRASS);
+    }
+}
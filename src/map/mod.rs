@@ -1,4 +1,10 @@
+pub mod atlas;
+pub mod autotile;
+pub mod doors;
+pub mod dungeon;
 pub mod generators;
+pub mod pathfinding;
+pub mod render_cache;
 pub mod tiles;
 
 use crate::map::tiles::Position;
@@ -99,3 +105,28 @@ impl Default for Rect {
         }
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use crate::map::tiles::Tile;
+
+    /// Build the tiles of a small map from rows of characters, the first row is y = 0.
+    /// `#` wall, `.` grass, `+` closed door, `L` locked door, `'` open door.
+    pub fn tiles_from_rows(rows: &[&str]) -> Vec<Vec<Tile>> {
+        let width = rows.first().map_or(0, |row| row.len());
+        (0..width)
+            .map(|x| {
+                rows.iter()
+                    .map(|row| match row.as_bytes()[x] {
+                        b'#' => Tile::WALL,
+                        b'.' => Tile::GRASS,
+                        b'+' => Tile::CLOSED_DOOR,
+                        b'L' => Tile::LOCKED_DOOR,
+                        b'\'' => Tile::OPEN_DOOR,
+                        other => panic!("unknown tile {}", other as char),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
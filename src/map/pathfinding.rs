@@ -0,0 +1,169 @@
+use crate::map::tiles::{DoorState, Position, Tile, DIRECTIONS};
+use std::cmp::{max, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+/// Check if the position is inside the map, revealed and walkable.
+/// Closed doors count as walkable, they can be opened on the way.
+fn is_passable(map: &Vec<Vec<Tile>>, revealed_tiles: &Vec<Vec<bool>>, pos: Position) -> bool {
+    if pos.x < 0 || pos.y < 0 {
+        return false;
+    }
+    let (x, y) = (pos.x as usize, pos.y as usize);
+    match (map.get(x).and_then(|row| row.get(y)), revealed_tiles.get(x)) {
+        (Some(tile), Some(row)) => {
+            let passable = tile.is_walkable() || tile.door() == Some(DoorState::Closed);
+            passable && row.get(y).copied().unwrap_or(false)
+        }
+        _ => false,
+    }
+}
+
+/// Check if the diagonal step from `from` to `to` goes past the corner of a wall,
+/// i.e. one of the two tiles beside the step is a wall.
+pub fn cuts_wall_corner(map: &[Vec<Tile>], from: Position, to: Position) -> bool {
+    if from.x == to.x || from.y == to.y {
+        return false;
+    }
+    let is_wall = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && map
+                .get(x as usize)
+                .and_then(|row| row.get(y as usize))
+                .is_some_and(|tile| *tile == Tile::WALL)
+    };
+    is_wall(to.x, from.y) || is_wall(from.x, to.y)
+}
+
+/// Steps needed to get from `from` to `to` when diagonal steps are allowed.
+fn distance(from: Position, to: Position) -> i32 {
+    max((from.x - to.x).abs(), (from.y - to.y).abs())
+}
+
+/// Find the cheapest path over revealed walkable tiles with A*, using the tile movement costs.
+/// Without `corner_cutting` the diagonal steps past the corner of a wall are skipped.
+/// The returned steps exclude `from` and end with `to`.
+pub fn find_path(
+    map: &Vec<Vec<Tile>>,
+    revealed_tiles: &Vec<Vec<bool>>,
+    from: Position,
+    to: Position,
+    corner_cutting: bool,
+) -> Option<Vec<Position>> {
+    if !is_passable(map, revealed_tiles, to) {
+        return None;
+    }
+    if from == to {
+        return Some(Vec::new());
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<Position, Position> = HashMap::new();
+    let mut cost: HashMap<Position, i32> = HashMap::new();
+    open.push(Reverse((distance(from, to), from.x, from.y)));
+    cost.insert(from, 0);
+
+    while let Some(Reverse((_, x, y))) = open.pop() {
+        let current = Position { x, y };
+        if current == to {
+            let mut path = vec![current];
+            let mut step = current;
+            while let Some(&previous) = came_from.get(&step) {
+                if previous == from {
+                    break;
+                }
+                path.push(previous);
+                step = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let current_cost = cost[&current];
+        for (dx, dy) in DIRECTIONS.iter() {
+            let next = Position {
+                x: current.x + dx,
+                y: current.y + dy,
+            };
+            if !is_passable(map, revealed_tiles, next)
+                || (!corner_cutting && cuts_wall_corner(map, current, next))
+            {
+                continue;
+            }
+            let next_cost = current_cost + map[next.x as usize][next.y as usize].movement_cost();
+            if cost.get(&next).is_none_or(|&known| next_cost < known) {
+                cost.insert(next, next_cost);
+                came_from.insert(next, current);
+                open.push(Reverse((next_cost + distance(next, to), next.x, next.y)));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::tests::tiles_from_rows;
+
+    const fn pos(x: i32, y: i32) -> Position {
+        Position { x, y }
+    }
+
+    fn all_revealed(map: &[Vec<Tile>]) -> Vec<Vec<bool>> {
+        map.iter().map(|row| vec![true; row.len()]).collect()
+    }
+
+    /// A room split by a wall, the way around goes through the gap at the bottom.
+    #[rustfmt::skip]
+    const SPLIT_ROOM: [&str; 5] = [
+        "#######",
+        "#..#..#",
+        "#..#..#",
+        "#.....#",
+        "#######",
+    ];
+
+    #[test]
+    fn straight_path() {
+        let map = tiles_from_rows(&["#######", "#.....#", "#######"]);
+        let path = find_path(&map, &all_revealed(&map), pos(1, 1), pos(5, 1), true);
+        assert_eq!(path, Some(vec![pos(2, 1), pos(3, 1), pos(4, 1), pos(5, 1)]));
+    }
+
+    #[test]
+    fn path_around_a_wall() {
+        let map = tiles_from_rows(&SPLIT_ROOM);
+        let path = find_path(&map, &all_revealed(&map), pos(1, 1), pos(5, 1), true);
+        assert_eq!(path, Some(vec![pos(2, 2), pos(3, 3), pos(4, 2), pos(5, 1)]));
+    }
+
+    #[test]
+    fn no_path_to_an_unreachable_or_unrevealed_goal() {
+        let walled_off = tiles_from_rows(&["#####", "#.#.#", "#####"]);
+        let revealed = all_revealed(&walled_off);
+        assert_eq!(
+            find_path(&walled_off, &revealed, pos(1, 1), pos(3, 1), true),
+            None
+        );
+
+        let map = tiles_from_rows(&["#######", "#.....#", "#######"]);
+        let mut revealed = all_revealed(&map);
+        revealed[5][1] = false;
+        assert_eq!(find_path(&map, &revealed, pos(1, 1), pos(5, 1), true), None);
+    }
+
+    #[test]
+    fn no_corner_cutting() {
+        let map = tiles_from_rows(&SPLIT_ROOM);
+        let path = find_path(&map, &all_revealed(&map), pos(1, 1), pos(5, 1), false)
+            .expect("a path through the gap");
+        assert_eq!(path.len(), 6, "{path:?}");
+        let starts = std::iter::once(pos(1, 1)).chain(path.iter().copied());
+        let cutting: Vec<(Position, Position)> = starts
+            .zip(path.iter().copied())
+            .filter(|(from, to)| cuts_wall_corner(&map, *from, *to))
+            .collect();
+        assert_eq!(cutting, Vec::new());
+    }
+}
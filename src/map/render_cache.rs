@@ -0,0 +1,123 @@
+use crate::map::atlas::SpriteManager;
+use crate::map::autotile::AutotileMasks;
+use crate::map::tiles::{Position, Tile};
+use macroquad::{
+    draw_rectangle, draw_texture_ex, render_target, set_camera, vec2, Camera2D, DrawTextureParams,
+    RenderTarget, BLACK, GRAY, WHITE,
+};
+use std::collections::HashSet;
+
+/// Pixels per tile in the cached texture.
+const TILE_PIXELS: u32 = 32;
+/// Largest side of the cached texture, bigger maps get fewer pixels per tile.
+const MAX_TEXTURE_SIZE: u32 = 8192;
+
+/// What a tile of the cached texture shows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Cell {
+    Hidden,
+    /// A revealed tile with its autotile mask, the ones in view are drawn brighter.
+    Revealed {
+        tile: Tile,
+        mask: u8,
+        visible: bool,
+    },
+}
+
+/// The revealed map baked into a texture. The cache remembers what it drew on every tile
+/// and only draws the tiles again that changed since, once the camera can see them.
+/// It keeps no queue in the game logic, which runs without a renderer as well.
+#[derive(Clone, Debug, Default)]
+pub struct MapRenderCache {
+    target: Option<RenderTarget>,
+    /// What is drawn on the texture, `drawn[x][y]`. `None` for the tiles never drawn.
+    drawn: Vec<Vec<Option<Cell>>>,
+}
+
+impl MapRenderCache {
+    /// Create the texture and forget what was drawn if the map size changed.
+    fn prepare(&mut self, width: usize, height: usize) -> RenderTarget {
+        let same_size =
+            self.drawn.len() == width && self.drawn.first().map_or(0, Vec::len) == height;
+        if let (Some(target), true) = (self.target, same_size) {
+            return target;
+        }
+        let pixels = TILE_PIXELS.min(MAX_TEXTURE_SIZE / width.max(height).max(1) as u32);
+        let target = render_target(width as u32 * pixels, height as u32 * pixels);
+        self.target = Some(target);
+        self.drawn = vec![vec![None; height]; width];
+        target
+    }
+
+    /// Draw the tiles inside `bounds` that changed since they were drawn into the texture.
+    pub fn update(
+        &mut self,
+        map: &[Vec<Tile>],
+        revealed_tiles: &[Vec<bool>],
+        visible_tiles: &[Position],
+        sprites: &mut SpriteManager,
+        masks: &AutotileMasks,
+        bounds: (Position, Position),
+    ) {
+        let width = map.len();
+        let height = map.first().map_or(0, Vec::len);
+        let target = self.prepare(width, height);
+        let visible: HashSet<Position> = visible_tiles.iter().copied().collect();
+
+        // The texture is y-up like the world, one tile is one unit.
+        let camera = Camera2D {
+            target: vec2(width as f32 / 2.0, height as f32 / 2.0),
+            zoom: vec2(2.0 / width as f32, 2.0 / height as f32),
+            render_target: Some(target),
+            ..Camera2D::default()
+        };
+        set_camera(camera);
+        let (min, max) = bounds;
+        let (start_x, end_x) = (min.x.max(0) as usize, (max.x + 1).max(0) as usize);
+        let (start_y, end_y) = (min.y.max(0) as usize, (max.y + 1).max(0) as usize);
+        for (x, row) in self.drawn.iter_mut().enumerate().take(end_x).skip(start_x) {
+            for (y, drawn) in row.iter_mut().enumerate().take(end_y).skip(start_y) {
+                let pos = Position {
+                    x: x as i32,
+                    y: y as i32,
+                };
+                let cell = if revealed_tiles[x][y] {
+                    Cell::Revealed {
+                        tile: map[x][y],
+                        mask: masks.get(pos),
+                        visible: visible.contains(&pos),
+                    }
+                } else {
+                    Cell::Hidden
+                };
+                if *drawn == Some(cell) {
+                    continue;
+                }
+                *drawn = Some(cell);
+                draw_rectangle(pos.x as f32, pos.y as f32, 1.0, 1.0, BLACK);
+                if let Cell::Revealed {
+                    tile,
+                    mask,
+                    visible,
+                } = cell
+                {
+                    // Remembered tiles are drawn darker.
+                    let color = if visible { WHITE } else { GRAY };
+                    sprites.draw_autotile(&tile, &pos, color, Some(mask));
+                }
+            }
+        }
+        sprites.draw_glyphs(camera);
+    }
+
+    /// Draw the cached texture over the map. (in camera space)
+    pub fn draw(&self, width: usize, height: usize) {
+        if let Some(target) = self.target {
+            let params = DrawTextureParams {
+                dest_size: Some(vec2(width as f32, height as f32)),
+                ..DrawTextureParams::default()
+            };
+            draw_texture_ex(target.texture, 0.0, 0.0, WHITE, params);
+        }
+    }
+}
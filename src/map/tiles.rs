@@ -1,98 +1,125 @@
 use crate::map::Point;
-use macroquad::{draw_texture_ex, vec2, Color, DrawTextureParams, Rect, Texture2D, Vec2};
-/// Available kinds of Tiles. `value()` is their position on the `TileAtlas`.
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum Tile {
-    Debug,
-    Wall,
-    Grass,
-    Pengu,
-    Door,
-    Chest,
-    Coin,
-    Cat,
-}
+use crate::raws::raws;
+use macroquad::{vec2, Color, Vec2};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A kind of map tile, the key of its definition in the raws. The sprite and every
+/// property come from the raws, a new kind of tile only needs a new entry there.
+/// The constants are the tiles the map generators place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Tile(&'static str);
 
 impl Tile {
-    /// Get their position on the `TileAtlas`.
-    const fn value(self) -> (f32, f32) {
-        match self {
-            Self::Debug => (0., 0.),
-            Self::Wall => (1., 0.),
-            Self::Grass => (2., 0.),
-            Self::Pengu => (3., 0.),
-            Self::Door => (0., 1.),
-            Self::Chest => (1., 1.),
-            Self::Coin => (2., 1.),
-            Self::Cat => (3., 1.),
-        }
+    pub const DEBUG: Self = Self("debug");
+    pub const WALL: Self = Self("wall");
+    pub const GRASS: Self = Self("grass");
+    pub const OPEN_DOOR: Self = Self("open_door");
+    pub const CLOSED_DOOR: Self = Self("closed_door");
+    pub const LOCKED_DOOR: Self = Self("locked_door");
+    pub const CHEST: Self = Self("chest");
+    pub const STAIRS_DOWN: Self = Self("stairs_down");
+    pub const STAIRS_UP: Self = Self("stairs_up");
+
+    /// Create the tile with a key from the raws.
+    pub const fn from_key(key: &'static str) -> Self {
+        Self(key)
     }
+
+    /// The key of the tile in the raws.
+    pub const fn key(self) -> &'static str {
+        self.0
+    }
+
     /// Check if entities can walk on that tile.
-    pub const fn is_walkable(self) -> bool {
-        match self {
-            Self::Debug => true,
-            Self::Wall => false,
-            Self::Grass => true,
-            Self::Pengu => false,
-            Self::Door => false,
-            Self::Chest => false,
-            Self::Coin => true,
-            Self::Cat => false,
-        }
+    pub fn is_walkable(self) -> bool {
+        raws().tile(self).walkable
     }
 
     /// Check if you can see through that tile.
-    pub const fn is_opaque(self) -> bool {
-        match self {
-            Self::Debug => false,
-            Self::Wall => true,
-            Self::Grass => false,
-            Self::Pengu => false,
-            Self::Door => true,
-            Self::Chest => true,
-            Self::Coin => false,
-            Self::Cat => false,
-        }
+    pub fn is_opaque(self) -> bool {
+        raws().tile(self).opaque
+    }
+
+    /// Turns it takes to walk over that tile.
+    pub fn movement_cost(self) -> i32 {
+        raws().tile(self).movement_cost.max(1)
+    }
+
+    /// Color the tile is drawn with when it is in view.
+    pub fn tint(self) -> Color {
+        Color(raws().tile(self).tint)
     }
-}
 
-/// Is used to split one `Texture2D` into different tiles.
-#[derive(Clone, Debug)]
-pub struct TileAtlas {
-    texture: Texture2D,
-    tile_width: f32,
-    tile_height: f32,
+    /// The state of the door, `None` if the tile is not a door.
+    pub fn door(self) -> Option<DoorState> {
+        raws().tile(self).door
+    }
 }
 
-impl TileAtlas {
-    /// Initialize the atlas from the texture and tile size.
-    pub const fn new(texture: Texture2D, tile_width: f32, tile_height: f32) -> Self {
-        Self {
-            texture,
-            tile_width,
-            tile_height,
-        }
+/// Tiles are saved with their key.
+impl Serialize for Tile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
     }
+}
 
-    /// Draw provided Tiles kind (e.g. `Tiles::Grass`) to the given position.
-    pub fn draw_tile(&self, tile: &Tile, pos: &Position, color: Color) {
-        let (atlas_position_x, atlas_position_y) = tile.value();
-        let params = DrawTextureParams {
-            dest_size: Some(Vec2::one()),
-            source: Some(Rect {
-                x: (self.tile_width + 0.2) * atlas_position_x,
-                y: self.tile_height * atlas_position_y,
-                w: self.tile_width - 1.0,
-                h: self.tile_height - 1.0,
-            }),
-            rotation: std::f32::consts::PI,
-        };
-        draw_texture_ex(self.texture, pos.x as f32, pos.y as f32, color, params);
+/// Only the tiles defined in the raws can be loaded.
+impl<'de> Deserialize<'de> for Tile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        raws()
+            .tile_named(&key)
+            .ok_or_else(|| D::Error::custom(format!("there is no tile {key} in the raws")))
     }
 }
 
+/// State of a door tile, set by the `door` field of its raws.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum DoorState {
+    Open,
+    Closed,
+    /// Needs to be picked before it can be opened.
+    Locked,
+}
+
+/// How an entity looks: its sprite in the atlas and its glyph in the glyphs render mode.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sprite {
+    /// Name of the sprite in the atlas descriptor.
+    pub name: String,
+    pub glyph: char,
+    /// Color of the glyph as RGBA.
+    pub glyph_color: [u8; 4],
+}
+
+/// Color the entity's sprite is drawn with, as RGBA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tint {
+    pub color: [u8; 4],
+}
+
+/// Draw order of the entities, the later layers are drawn on top.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SpriteLayer {
+    Items,
+    Creatures,
+}
+
+/// Steps to the 8 neighbouring tiles, (x, y) with y up, clockwise from north.
+pub const DIRECTIONS: [(i32, i32); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
 /// The coordinates on the world grid.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
@@ -113,15 +140,18 @@ impl From<Point> for Position {
     }
 }
 
+impl From<Vec2> for Position {
+    /// Get the tile under the given world coordinates.
+    fn from(point: Vec2) -> Self {
+        Self {
+            x: point.x().floor() as i32,
+            y: point.y().floor() as i32,
+        }
+    }
+}
+
 impl Into<Vec2> for Position {
     fn into(self) -> Vec2 {
         vec2(self.x as f32, self.y as f32)
     }
 }
-
-/// Used for drawing the texture in macroquad. Points to the tile in atlas.
-#[derive(Clone, Copy, Debug, PartialEq)]
-struct AtlasPosition {
-    x: f32,
-    y: f32,
-}
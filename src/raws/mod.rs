@@ -0,0 +1,494 @@
+use crate::characters::monster::{IsMonster, Name};
+use crate::characters::player::Viewshed;
+use crate::characters::stats::CombatStats;
+use crate::items::effects::{
+    AreaOfEffect, Confusion, Consumable, InflictsDamage, ProvidesHealing, Ranged,
+};
+use crate::items::{Gold, Item};
+use crate::map::tiles::{DoorState, Position, Sprite, SpriteLayer, Tile, Tint};
+use fastrand::Rng;
+use legion::systems::CommandBuffer;
+use legion::Entity;
+use macroquad::error;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+
+/// Compiled in copy of the raws, used when the file can't be loaded.
+const DEFAULT_RAWS: &str = include_str!("../../assets/raws.json");
+
+/// Definition of a map tile kind.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct TileRaw {
+    /// Key of the tile, see `Tile`.
+    pub tile: String,
+    pub name: String,
+    pub description: String,
+    /// Name of the sprite in the atlas descriptor.
+    pub sprite: String,
+    pub walkable: bool,
+    pub opaque: bool,
+    /// Turns it takes to walk over the tile, used by the pathfinding.
+    #[serde(default = "default_movement_cost")]
+    pub movement_cost: i32,
+    #[serde(default = "default_tint")]
+    pub tint: [u8; 4],
+    /// Neighbours in the same autotile group change the sprite to `<sprite>_<mask>`.
+    #[serde(default)]
+    pub autotile: Option<String>,
+    /// Character drawn instead of the sprite in the glyphs render mode.
+    #[serde(default = "default_glyph")]
+    pub glyph: char,
+    /// Color of the glyph, the `tint` is used if it's missing.
+    #[serde(default)]
+    pub glyph_color: Option<[u8; 4]>,
+    /// Set for doors, bumping into a closed or locked door opens or picks it.
+    #[serde(default)]
+    pub door: Option<DoorState>,
+}
+
+const fn default_movement_cost() -> i32 {
+    1
+}
+
+const fn default_tint() -> [u8; 4] {
+    [255, 255, 255, 255]
+}
+
+const fn default_glyph() -> char {
+    '?'
+}
+
+/// Used for the tiles missing from the raws: drawn with the first sprite, walkable and transparent.
+static MISSING_TILE: TileRaw = TileRaw {
+    tile: String::new(),
+    name: String::new(),
+    description: String::new(),
+    sprite: String::new(),
+    walkable: true,
+    opaque: false,
+    movement_cost: 1,
+    tint: [255, 255, 255, 255],
+    autotile: None,
+    glyph: '?',
+    glyph_color: None,
+    door: None,
+};
+
+/// Hit points and other numbers of a monster.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub struct StatsRaw {
+    pub max_hp: i32,
+}
+
+/// Definition of a monster kind.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct MonsterRaw {
+    pub name: String,
+    pub display_name: String,
+    /// Name of the sprite in the atlas descriptor.
+    pub sprite: String,
+    /// Character drawn instead of the sprite in the glyphs render mode.
+    #[serde(default = "default_glyph")]
+    pub glyph: char,
+    /// Color of the glyph, the `color` is used if it's missing.
+    #[serde(default)]
+    pub glyph_color: Option<[u8; 4]>,
+    /// Tint of the sprite.
+    pub color: [u8; 4],
+    pub stats: StatsRaw,
+    pub vision_range: i32,
+}
+
+/// Amount of gold in a pile, rolled from `min` to `max`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub struct GoldRaw {
+    pub min: u32,
+    pub max: u32,
+}
+
+/// What happens when the item is used, every field is optional.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct EffectsRaw {
+    pub healing: Option<i32>,
+    pub damage: Option<i32>,
+    pub ranged: Option<i32>,
+    pub area_of_effect: Option<i32>,
+    pub confusion: Option<i32>,
+}
+
+/// Definition of an item kind.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ItemRaw {
+    pub name: String,
+    pub display_name: String,
+    pub description: String,
+    /// Name of the sprite in the atlas descriptor.
+    pub sprite: String,
+    /// Character drawn instead of the sprite in the glyphs render mode.
+    #[serde(default = "default_glyph")]
+    pub glyph: char,
+    /// Color of the glyph, the `color` is used if it's missing.
+    #[serde(default)]
+    pub glyph_color: Option<[u8; 4]>,
+    /// Tint of the sprite.
+    pub color: [u8; 4],
+    #[serde(default)]
+    pub consumable: bool,
+    #[serde(default)]
+    pub gold: Option<GoldRaw>,
+    #[serde(default)]
+    pub effects: EffectsRaw,
+}
+
+/// Where and how often a monster or item appears in the dungeon.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct SpawnRaw {
+    pub name: String,
+    /// First depth the entry appears on, counting from 1.
+    pub min_depth: usize,
+    /// Last depth the entry appears on, counting from 1.
+    pub max_depth: usize,
+    pub weight: u32,
+}
+
+/// What can fall out of a chest.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct LootRaw {
+    pub name: String,
+    pub weight: u32,
+}
+
+/// Contents of the raws file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Raws {
+    pub tiles: Vec<TileRaw>,
+    pub monsters: Vec<MonsterRaw>,
+    pub items: Vec<ItemRaw>,
+    pub spawn_table: Vec<SpawnRaw>,
+    pub chest_loot: Vec<LootRaw>,
+}
+
+/// The loaded raws, indexed by name.
+#[derive(Clone, Debug, Default)]
+pub struct RawMaster {
+    pub raws: Raws,
+    /// Checksum of the raws file, a replay checks it was recorded with the same raws.
+    pub checksum: u64,
+    tile_index: HashMap<String, usize>,
+    monster_index: HashMap<String, usize>,
+    item_index: HashMap<String, usize>,
+}
+
+static RAWS: OnceLock<RawMaster> = OnceLock::new();
+
+/// Load the raws file once at startup, later calls do nothing.
+pub fn init(path: &str) {
+    RAWS.get_or_init(|| RawMaster::init(path));
+}
+
+/// Get the loaded raws, loads the compiled in copy if `init()` was never called.
+pub fn raws() -> &'static RawMaster {
+    RAWS.get_or_init(|| RawMaster::parse(DEFAULT_RAWS).unwrap_or_default())
+}
+
+/// FNV-1a hash of the text, it is the same on every machine and build.
+fn checksum(contents: &str) -> u64 {
+    contents.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Pick a random name from `(name, weight)` pairs, respecting the weights.
+fn roll_weighted<'a>(
+    choices: impl Iterator<Item = (&'a str, u32)> + Clone,
+    rng: &Rng,
+) -> Option<&'a str> {
+    let total: u32 = choices.clone().map(|(_, weight)| weight).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut roll = rng.u32(..total);
+    for (name, weight) in choices {
+        if roll < weight {
+            return Some(name);
+        }
+        roll -= weight;
+    }
+    None
+}
+
+impl RawMaster {
+    /// Parse the raws and index them by name.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let raws: Raws = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+        let index = |names: Vec<&String>| {
+            names
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| (name.clone(), i))
+                .collect::<HashMap<String, usize>>()
+        };
+        let tile_index = index(raws.tiles.iter().map(|tile| &tile.tile).collect());
+        let monster_index = index(raws.monsters.iter().map(|monster| &monster.name).collect());
+        let item_index = index(raws.items.iter().map(|item| &item.name).collect());
+        Ok(Self {
+            raws,
+            checksum: checksum(contents),
+            tile_index,
+            monster_index,
+            item_index,
+        })
+    }
+
+    /// Try to load the raws file, use the compiled in raws otherwise.
+    pub fn init(path: &str) -> Self {
+        let loaded = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| Self::parse(&contents));
+        match loaded {
+            Ok(master) => master,
+            Err(e) => {
+                error!(
+                    "Unable to load raws at {}! Loading default raws. Error: {}",
+                    path, e
+                );
+                Self::parse(DEFAULT_RAWS).unwrap_or_default()
+            }
+        }
+    }
+
+    /// Get the definition of the `tile`, tiles missing from the raws get a placeholder.
+    pub fn tile(&self, tile: Tile) -> &TileRaw {
+        self.tile_index
+            .get(tile.key())
+            .map_or(&MISSING_TILE, |&i| &self.raws.tiles[i])
+    }
+
+    /// Get the tile with the `key`, `None` if the raws don't define it.
+    pub fn tile_named(&'static self, key: &str) -> Option<Tile> {
+        let &i = self.tile_index.get(key)?;
+        Some(Tile::from_key(&self.raws.tiles[i].tile))
+    }
+
+    /// Pick a random spawn table entry available on the (0 based) `depth`.
+    pub fn roll_spawn(&self, depth: usize, rng: &Rng) -> Option<&str> {
+        let available = self
+            .raws
+            .spawn_table
+            .iter()
+            .filter(|entry| (entry.min_depth..=entry.max_depth).contains(&(depth + 1)))
+            .map(|entry| (entry.name.as_str(), entry.weight));
+        roll_weighted(available, rng)
+    }
+
+    /// Pick a random item to put in a chest.
+    pub fn roll_chest_loot(&self, rng: &Rng) -> Option<&str> {
+        let loot = self
+            .raws
+            .chest_loot
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.weight));
+        roll_weighted(loot, rng)
+    }
+
+    /// Spawn the monster or item called `name` on the given position.
+    /// Returns `None` if there is no such monster or item in the raws.
+    pub fn spawn_named(
+        &self,
+        cmd: &mut CommandBuffer,
+        name: &str,
+        pos: Position,
+    ) -> Option<Entity> {
+        if let Some(&i) = self.monster_index.get(name) {
+            return Some(spawn_monster(cmd, &self.raws.monsters[i], pos));
+        }
+        if let Some(&i) = self.item_index.get(name) {
+            return Some(spawn_item(cmd, &self.raws.items[i], pos));
+        }
+        error!("There is no {} in the raws", name);
+        None
+    }
+}
+
+/// Spawn a monster from its definition.
+fn spawn_monster(cmd: &mut CommandBuffer, monster: &MonsterRaw, pos: Position) -> Entity {
+    cmd.push((
+        Sprite {
+            name: monster.sprite.clone(),
+            glyph: monster.glyph,
+            glyph_color: monster.glyph_color.unwrap_or(monster.color),
+        },
+        pos,
+        Tint {
+            color: monster.color,
+        },
+        SpriteLayer::Creatures,
+        IsMonster {},
+        Name {
+            name: monster.display_name.clone(),
+        },
+        CombatStats::new(monster.stats.max_hp),
+        Viewshed {
+            visible_tiles: Vec::new(),
+            range: monster.vision_range,
+            dirty: true,
+        },
+    ))
+}
+
+/// Spawn an item from its definition, with a component for every effect it has.
+fn spawn_item(cmd: &mut CommandBuffer, item: &ItemRaw, pos: Position) -> Entity {
+    let gold = item.gold.map(|range| Gold {
+        amount: Rng::new().u32(range.min..=range.max.max(range.min)),
+    });
+    let name = gold.map_or_else(
+        || item.display_name.clone(),
+        |pile| format!("{} {}", pile.amount, item.display_name),
+    );
+    let entity = cmd.push((
+        Sprite {
+            name: item.sprite.clone(),
+            glyph: item.glyph,
+            glyph_color: item.glyph_color.unwrap_or(item.color),
+        },
+        pos,
+        Tint { color: item.color },
+        SpriteLayer::Items,
+        Item {
+            name,
+            description: item.description.clone(),
+        },
+    ));
+    if let Some(pile) = gold {
+        cmd.add_component(entity, pile);
+    }
+    if item.consumable {
+        cmd.add_component(entity, Consumable {});
+    }
+    let effects = item.effects;
+    if let Some(heal_amount) = effects.healing {
+        cmd.add_component(entity, ProvidesHealing { heal_amount });
+    }
+    if let Some(damage) = effects.damage {
+        cmd.add_component(entity, InflictsDamage { damage });
+    }
+    if let Some(range) = effects.ranged {
+        cmd.add_component(entity, Ranged { range });
+    }
+    if let Some(radius) = effects.area_of_effect {
+        cmd.add_component(entity, AreaOfEffect { radius });
+    }
+    if let Some(turns) = effects.confusion {
+        cmd.add_component(entity, Confusion { turns });
+    }
+    entity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::atlas::AtlasDescriptor;
+
+    /// A spawn table with one entry for the shallow levels and one for the deep ones.
+    const DEPTH_RAWS: &str = r#"{
+        "spawn_table": [
+            { "name": "rat", "min_depth": 1, "max_depth": 2, "weight": 1 },
+            { "name": "orc", "min_depth": 3, "max_depth": 5, "weight": 1 }
+        ]
+    }"#;
+
+    #[test]
+    fn shipped_raws_parse() {
+        let contents = fs::read_to_string("assets/raws.json").expect("assets/raws.json");
+        let master = RawMaster::parse(&contents).expect("shipped raws are valid");
+        assert_eq!(master.checksum, checksum(DEFAULT_RAWS));
+        for tile in [
+            Tile::DEBUG,
+            Tile::WALL,
+            Tile::GRASS,
+            Tile::OPEN_DOOR,
+            Tile::CLOSED_DOOR,
+            Tile::LOCKED_DOOR,
+            Tile::CHEST,
+            Tile::STAIRS_DOWN,
+            Tile::STAIRS_UP,
+        ] {
+            assert!(
+                master.tile_index.contains_key(tile.key()),
+                "no raws for the {} tile",
+                tile.key()
+            );
+        }
+        let spawned = master.raws.spawn_table.iter().map(|entry| &entry.name);
+        let looted = master.raws.chest_loot.iter().map(|entry| &entry.name);
+        for name in spawned.chain(looted) {
+            assert!(
+                master.monster_index.contains_key(name) || master.item_index.contains_key(name),
+                "{} is neither a monster nor an item",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn rolls_follow_the_weights() {
+        let rng = Rng::with_seed(7);
+        let choices = [("rare", 1), ("common", 3), ("never", 0)];
+        let mut rare = 0;
+        let mut common = 0;
+        for _ in 0..4000 {
+            match roll_weighted(choices.iter().copied(), &rng) {
+                Some("rare") => rare += 1,
+                Some("common") => common += 1,
+                other => panic!("unexpected roll {:?}", other),
+            }
+        }
+        assert!((900..1100).contains(&rare), "rare: {}", rare);
+        assert!((2900..3100).contains(&common), "common: {}", common);
+
+        assert_eq!(roll_weighted(std::iter::once(("never", 0)), &rng), None);
+        assert_eq!(roll_weighted(std::iter::empty(), &rng), None);
+    }
+
+    #[test]
+    fn spawns_depend_on_the_depth() {
+        let master = RawMaster::parse(DEPTH_RAWS).expect("valid raws");
+        let rng = Rng::with_seed(7);
+        for _ in 0..100 {
+            // The depths of the spawn table count from 1, the dungeon counts from 0.
+            assert_eq!(master.roll_spawn(1, &rng), Some("rat"));
+            assert_eq!(master.roll_spawn(2, &rng), Some("orc"));
+            assert_eq!(master.roll_spawn(4, &rng), Some("orc"));
+        }
+        assert_eq!(master.roll_spawn(5, &rng), None);
+    }
+
+    #[test]
+    fn raws_sprites_are_in_the_atlas() {
+        let descriptor = AtlasDescriptor::default_descriptor();
+        let master = RawMaster::parse(DEFAULT_RAWS).expect("shipped raws are valid");
+        let raws = &master.raws;
+        let mut sprites: Vec<String> = raws.tiles.iter().map(|tile| tile.sprite.clone()).collect();
+        sprites.extend(raws.monsters.iter().map(|monster| monster.sprite.clone()));
+        sprites.extend(raws.items.iter().map(|item| item.sprite.clone()));
+        // The tiles named after their autotile group have a sprite for every neighbour mask.
+        let autotiled = raws
+            .tiles
+            .iter()
+            .filter(|tile| tile.autotile.as_ref() == Some(&tile.sprite));
+        for tile in autotiled {
+            sprites.extend((0..16).map(|mask| format!("{}_{mask}", tile.sprite)));
+        }
+        for sprite in sprites {
+            assert!(
+                descriptor.names.contains_key(&sprite),
+                "no {} sprite in the atlas",
+                sprite
+            );
+        }
+    }
+}
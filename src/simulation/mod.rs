@@ -0,0 +1,346 @@
+pub mod save;
+pub mod systems;
+
+use crate::characters::player::{IsPlayer, MoveRules, PlayerTurn, Travel, Viewshed};
+use crate::characters::stats::{delete_the_dead_system, CombatStats};
+use crate::input::{PlayerCommand, PlayerInput};
+use crate::items::{
+    drop_item_system,
+    effects::{use_item_system, wear_off_confusion_system},
+    item_collection_system, item_commands_system, pickup_item_system, Purse,
+};
+use crate::map::autotile::update_autotile_system;
+use crate::map::doors::TileChanges;
+use crate::map::dungeon::{build_level, enter_level, take_stairs, Dungeon};
+use crate::map::tiles::{Position, Sprite};
+use crate::utils::gamelog::GameLog;
+use crate::utils::settings::Settings;
+use legion::{Entity, EntityStore, Resources, Schedule, World};
+use systems::{
+    handle_command_system, mark_viewsheds_dirty_system, travel_system, update_viewshed_system,
+};
+
+/// Frame time of a tick stepped without a window, in seconds.
+pub const TURN_DELTA: f32 = 1.0 / 60.0;
+
+/// The game logic without a window: the world, its resources and the turn schedule.
+/// Ticks are stepped with player commands, nothing is drawn and no input is read,
+/// so it runs on a machine without a display.
+pub struct Simulation {
+    pub world: World,
+    pub resources: Resources,
+    schedule: Schedule,
+    settings: Settings,
+    pub player: Entity,
+    /// Turns the player took so far, see `PlayerTurn`.
+    pub turn: u64,
+}
+
+impl Simulation {
+    /// Generate the first level and insert the player.
+    /// Seed the random generator before for a repeatable run.
+    pub fn new(settings: Settings) -> Self {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let player = populate_world(&mut world, &mut resources, &settings);
+        Self {
+            world,
+            resources,
+            schedule: turn_schedule(),
+            settings,
+            player,
+            turn: 0,
+        }
+    }
+
+    /// Run one tick with the `command` of the player, `delta` is the frame time in seconds.
+    /// The turn counter only goes up when the player acted.
+    /// Returns where the player arrived when they took the stairs.
+    pub fn step(&mut self, command: Option<PlayerCommand>, delta: f32) -> Option<Position> {
+        if let Some(mut input) = self.resources.get_mut::<PlayerInput>() {
+            *input = PlayerInput { command, delta };
+        }
+        if let Some(mut turn) = self.resources.get_mut::<PlayerTurn>() {
+            turn.taken = command.is_some_and(PlayerCommand::takes_turn);
+        }
+        // The level transition needs the whole world, it isn't done by a system.
+        let arrival = if command == Some(PlayerCommand::TakeStairs) {
+            take_stairs(
+                &mut self.world,
+                &mut self.resources,
+                &self.settings,
+                self.player,
+            )
+        } else {
+            None
+        };
+        self.schedule.execute(&mut self.world, &mut self.resources);
+        if self
+            .resources
+            .get::<PlayerTurn>()
+            .is_some_and(|turn| turn.taken)
+        {
+            self.turn += 1;
+        }
+        arrival
+    }
+
+    pub fn player_position(&self) -> Option<Position> {
+        let entry = self.world.entry_ref(self.player).ok()?;
+        entry.get_component::<Position>().ok().copied()
+    }
+}
+
+/// The systems of one turn, rendering has its own schedule.
+fn turn_schedule() -> Schedule {
+    Schedule::builder()
+        .add_system(update_viewshed_system())
+        .add_system(handle_command_system())
+        .add_system(pickup_item_system())
+        .add_system(item_commands_system())
+        .flush()
+        .add_system(item_collection_system())
+        .add_system(use_item_system())
+        .add_system(drop_item_system())
+        .flush()
+        .add_system(delete_the_dead_system())
+        .add_system(travel_system())
+        .add_system(wear_off_confusion_system())
+        .add_system(update_autotile_system())
+        .add_system(mark_viewsheds_dirty_system())
+        .build()
+}
+
+/// Insert the resources that only live for the current turn or come from the settings,
+/// they are not saved.
+fn insert_turn_resources(resources: &mut Resources, settings: &Settings) {
+    resources.insert(Travel::default());
+    resources.insert(TileChanges::default());
+    resources.insert(PlayerInput::default());
+    resources.insert(PlayerTurn::default());
+    resources.insert(MoveRules {
+        corner_cutting: settings.corner_cutting,
+    });
+}
+
+/// Generate the first level and insert the player.
+/// Returns the player entity.
+fn populate_world(world: &mut World, resources: &mut Resources, settings: &Settings) -> Entity {
+    let level = build_level(settings, 0);
+    let starting_position = level.map.entrance;
+    enter_level(level, world, resources);
+    resources.insert(Dungeon::default());
+    resources.insert(GameLog::default());
+    insert_turn_resources(resources, settings);
+
+    // Insert the player into the world.
+    world.push((
+        Sprite {
+            name: "pengu".to_string(),
+            glyph: '@',
+            glyph_color: [255, 255, 255, 255],
+        },
+        starting_position,
+        IsPlayer {},
+        Viewshed {
+            visible_tiles: Vec::new(),
+            range: 8,
+            dirty: true,
+        },
+        Purse::default(),
+        CombatStats::new(30),
+    ))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::characters::monster::IsMonster;
+    use crate::map::pathfinding::find_path;
+    use crate::map::tiles::{Tile, DIRECTIONS};
+    use legion::IntoQuery;
+
+    pub const SEED: u64 = 42;
+
+    pub fn new_simulation(seed: u64) -> Simulation {
+        fastrand::seed(seed);
+        Simulation::new(Settings::default())
+    }
+
+    fn tile_at(sim: &Simulation, pos: Position) -> Option<Tile> {
+        let map = sim.resources.get::<Vec<Vec<Tile>>>()?;
+        map.get(pos.x as usize)?.get(pos.y as usize).copied()
+    }
+
+    pub fn depth(sim: &Simulation) -> usize {
+        sim.resources.get::<Dungeon>().expect("dungeon").depth
+    }
+
+    pub fn find_tile(sim: &Simulation, wanted: Tile) -> Option<Position> {
+        let map = sim.resources.get::<Vec<Vec<Tile>>>()?;
+        map.iter().enumerate().find_map(|(x, row)| {
+            let y = row.iter().position(|tile| *tile == wanted)?;
+            Some(Position {
+                x: x as i32,
+                y: y as i32,
+            })
+        })
+    }
+
+    /// Everything a run can change: the turn, the map, the log and where the entities are.
+    pub type Snapshot = (u64, Vec<Vec<Tile>>, Vec<String>, Vec<(i32, i32, String)>);
+
+    pub fn snapshot(sim: &Simulation) -> Snapshot {
+        let map = sim.resources.get::<Vec<Vec<Tile>>>().expect("map").clone();
+        let log = sim.resources.get::<GameLog>().expect("log").entries.clone();
+        let mut entities: Vec<(i32, i32, String)> = <(&Position, &Sprite)>::query()
+            .iter(&sim.world)
+            .map(|(pos, sprite)| (pos.x, pos.y, sprite.name.clone()))
+            .collect();
+        entities.sort();
+        (sim.turn, map, log, entities)
+    }
+
+    /// Put the player on `pos` without taking a turn.
+    pub fn teleport(sim: &mut Simulation, pos: Position) {
+        if let Some(mut entry) = sim.world.entry(sim.player) {
+            *entry
+                .get_component_mut::<Position>()
+                .expect("player position") = pos;
+        }
+    }
+
+    #[test]
+    fn move_and_wait() {
+        let mut sim = new_simulation(SEED);
+        let start = sim.player_position().expect("player position");
+        let (dx, dy) = DIRECTIONS
+            .iter()
+            .copied()
+            .find(|(dx, dy)| {
+                let next = Position {
+                    x: start.x + dx,
+                    y: start.y + dy,
+                };
+                tile_at(&sim, next).is_some_and(Tile::is_walkable)
+            })
+            .expect("a walkable tile next to the entrance");
+
+        sim.step(Some(PlayerCommand::Move { dx, dy }), TURN_DELTA);
+        let moved = Position {
+            x: start.x + dx,
+            y: start.y + dy,
+        };
+        assert_eq!(sim.player_position(), Some(moved));
+        assert_eq!(sim.turn, 1);
+
+        sim.step(Some(PlayerCommand::Wait), TURN_DELTA);
+        assert_eq!(sim.player_position(), Some(moved));
+        assert_eq!(sim.turn, 2);
+
+        // Ticks without a command are not turns.
+        sim.step(None, TURN_DELTA);
+        assert_eq!(sim.turn, 2);
+    }
+
+    #[test]
+    fn take_stairs_down_and_up() {
+        let mut sim = new_simulation(SEED);
+        let stairs = find_tile(&sim, Tile::STAIRS_DOWN).expect("stairs down on the first level");
+        teleport(&mut sim, stairs);
+
+        let arrival = sim
+            .step(Some(PlayerCommand::TakeStairs), TURN_DELTA)
+            .expect("the player took the stairs");
+        assert_eq!(depth(&sim), 1);
+        assert_eq!(sim.player_position(), Some(arrival));
+        assert_eq!(tile_at(&sim, arrival), Some(Tile::STAIRS_UP));
+
+        let back = sim
+            .step(Some(PlayerCommand::TakeStairs), TURN_DELTA)
+            .expect("the player took the stairs back");
+        assert_eq!(depth(&sim), 0);
+        assert_eq!(back, stairs);
+        assert_eq!(sim.turn, 2);
+    }
+
+    #[test]
+    fn travel_stops_for_a_monster_that_was_not_in_view() {
+        let mut sim = new_simulation(SEED);
+        // The view is computed on the first tick.
+        sim.step(None, TURN_DELTA);
+        let start = sim.player_position().expect("player position");
+        let visible = sim
+            .world
+            .entry_ref(sim.player)
+            .expect("player")
+            .get_component::<Viewshed>()
+            .expect("player viewshed")
+            .visible_tiles
+            .clone();
+        let path_to = |to: Position| {
+            let map = sim.resources.get::<Vec<Vec<Tile>>>()?;
+            let revealed = sim.resources.get::<Vec<Vec<bool>>>()?;
+            find_path(&map, &revealed, start, to, true)
+        };
+        let (destination, path) = visible
+            .iter()
+            .find_map(|pos| Some((*pos, path_to(*pos).filter(|path| path.len() > 2)?)))
+            .expect("a tile in view a few steps away");
+        let mut aside = visible
+            .iter()
+            .copied()
+            .filter(|pos| *pos != start && !path.contains(pos));
+
+        // One monster is in view when the travel starts.
+        let seen = sim
+            .world
+            .push((IsMonster {}, aside.next().expect("a tile for a monster")));
+        sim.step(Some(PlayerCommand::TravelTo(destination)), TURN_DELTA);
+        assert_eq!(
+            sim.resources.get::<Travel>().expect("travel").destination,
+            Some(destination)
+        );
+
+        // It walks away as another one shows up, the count of monsters in view stays the same.
+        if let Some(mut entry) = sim.world.entry(seen) {
+            *entry
+                .get_component_mut::<Position>()
+                .expect("monster position") = Position { x: -1, y: -1 };
+        }
+        sim.world
+            .push((IsMonster {}, aside.next().expect("a tile for a monster")));
+        sim.step(None, TURN_DELTA);
+        assert_eq!(
+            sim.resources.get::<Travel>().expect("travel").destination,
+            None
+        );
+        assert_eq!(sim.player_position(), Some(start));
+    }
+
+    #[test]
+    fn same_seed_same_run() {
+        let commands = [
+            Some(PlayerCommand::Move { dx: 1, dy: 0 }),
+            Some(PlayerCommand::Move { dx: 0, dy: 1 }),
+            Some(PlayerCommand::Wait),
+            Some(PlayerCommand::Pickup),
+            Some(PlayerCommand::Move { dx: -1, dy: -1 }),
+            Some(PlayerCommand::TravelTo(Position { x: 5, y: 5 })),
+            None,
+            None,
+            Some(PlayerCommand::TakeStairs),
+        ];
+        let run = || {
+            let mut sim = new_simulation(SEED);
+            for command in commands {
+                sim.step(command, TURN_DELTA);
+            }
+            for _ in 0..100 {
+                sim.step(None, TURN_DELTA);
+            }
+            snapshot(&sim)
+        };
+        assert_eq!(run(), run());
+    }
+}
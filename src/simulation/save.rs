@@ -0,0 +1,298 @@
+use super::{insert_turn_resources, turn_schedule, Simulation};
+use crate::characters::monster::{IsMonster, Name};
+use crate::characters::player::{IsPlayer, Viewshed};
+use crate::characters::stats::CombatStats;
+use crate::items::effects::{
+    AreaOfEffect, Confused, Confusion, Consumable, InflictsDamage, ProvidesHealing, Ranged,
+};
+use crate::items::{
+    Gold, InBackpack, Item, Purse, WantsToDropItem, WantsToPickupItem, WantsToUseItem,
+};
+use crate::map::dungeon::{enter_level, Dungeon, Level};
+use crate::map::generators::Map;
+use crate::map::tiles::{Position, Sprite, SpriteLayer, Tile, Tint};
+use crate::utils::gamelog::GameLog;
+use crate::utils::settings::Settings;
+use legion::{any, component, Entity, IntoQuery, Registry, Resources, World};
+use serde::de::DeserializeSeed;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+
+/// Version of the save format, saves of other versions can't be loaded.
+const VERSION: u32 = 3;
+
+/// Everything needed to go on with the game where it was saved.
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    /// State of the random generator, the game goes on as if it was never saved.
+    rng_state: u64,
+    turn: u64,
+    depth: usize,
+    log: Vec<String>,
+    /// The level being played.
+    current: SavedLevel,
+    /// The other visited levels by depth, `None` for the current one and the levels never visited.
+    levels: Vec<Option<SavedLevel>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedLevel {
+    tiles: Vec<Vec<Tile>>,
+    revealed_tiles: Vec<Vec<bool>>,
+    /// The entities of the level as serialized by legion.
+    entities: Value,
+}
+
+/// The components that are saved, with their names in the save file.
+/// An entity reference in a component stays valid as long as the same registry is used
+/// for every world of the save.
+fn registry() -> Registry<String> {
+    let mut registry = Registry::<String>::default();
+    registry.register::<Sprite>("sprite".to_string());
+    registry.register::<Position>("position".to_string());
+    registry.register::<Tint>("tint".to_string());
+    registry.register::<SpriteLayer>("sprite_layer".to_string());
+    registry.register::<IsPlayer>("is_player".to_string());
+    registry.register::<IsMonster>("is_monster".to_string());
+    registry.register::<Name>("name".to_string());
+    registry.register::<Viewshed>("viewshed".to_string());
+    registry.register::<CombatStats>("combat_stats".to_string());
+    registry.register::<Purse>("purse".to_string());
+    registry.register::<Item>("item".to_string());
+    registry.register::<InBackpack>("in_backpack".to_string());
+    registry.register::<Gold>("gold".to_string());
+    registry.register::<WantsToPickupItem>("wants_to_pickup_item".to_string());
+    registry.register::<WantsToUseItem>("wants_to_use_item".to_string());
+    registry.register::<WantsToDropItem>("wants_to_drop_item".to_string());
+    registry.register::<Consumable>("consumable".to_string());
+    registry.register::<ProvidesHealing>("provides_healing".to_string());
+    registry.register::<InflictsDamage>("inflicts_damage".to_string());
+    registry.register::<Ranged>("ranged".to_string());
+    registry.register::<AreaOfEffect>("area_of_effect".to_string());
+    registry.register::<Confusion>("confusion".to_string());
+    registry.register::<Confused>("confused".to_string());
+    registry
+}
+
+fn save_level(
+    registry: &Registry<String>,
+    tiles: &[Vec<Tile>],
+    revealed_tiles: &[Vec<bool>],
+    world: &World,
+) -> Result<SavedLevel, String> {
+    let entities =
+        serde_json::to_value(world.as_serializable(any(), registry)).map_err(|e| e.to_string())?;
+    Ok(SavedLevel {
+        tiles: tiles.to_vec(),
+        revealed_tiles: revealed_tiles.to_vec(),
+        entities,
+    })
+}
+
+fn load_level(registry: &Registry<String>, saved: SavedLevel) -> Result<Level, String> {
+    let world = registry
+        .as_deserialize()
+        .deserialize(saved.entities)
+        .map_err(|e| e.to_string())?;
+    Ok(Level {
+        map: Map::from_parts(saved.tiles, saved.revealed_tiles),
+        world,
+    })
+}
+
+/// Save the whole game to the file at `path`.
+pub fn save_game(sim: &Simulation, path: &str) -> Result<(), String> {
+    let registry = registry();
+    let tiles = sim
+        .resources
+        .get::<Vec<Vec<Tile>>>()
+        .ok_or("there is no map")?;
+    let revealed_tiles = sim
+        .resources
+        .get::<Vec<Vec<bool>>>()
+        .ok_or("there are no revealed tiles")?;
+    let current = save_level(&registry, &tiles, &revealed_tiles, &sim.world)?;
+    let dungeon = sim
+        .resources
+        .get::<Dungeon>()
+        .ok_or("there is no dungeon")?;
+    let levels = dungeon
+        .levels()
+        .iter()
+        .map(|stored| {
+            stored
+                .as_ref()
+                .map(|level| {
+                    save_level(
+                        &registry,
+                        &level.map.tiles,
+                        &level.map.revealed_tiles,
+                        &level.world,
+                    )
+                })
+                .transpose()
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let log = sim
+        .resources
+        .get::<GameLog>()
+        .map(|log| log.entries.clone())
+        .unwrap_or_default();
+    let save = SaveFile {
+        version: VERSION,
+        rng_state: fastrand::get_seed(),
+        turn: sim.turn,
+        depth: dungeon.depth,
+        log,
+        current,
+        levels,
+    };
+    let json = serde_json::to_string(&save).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+/// Load a game saved with `save_game()`. The random generator is restored as well.
+pub fn load_game(path: &str, settings: Settings) -> Result<Simulation, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let value: Value = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+    let version = value.get("version").and_then(Value::as_u64);
+    if version != Some(u64::from(VERSION)) {
+        return Err(format!(
+            "save version {version:?} is not supported, expected {VERSION}"
+        ));
+    }
+    let save: SaveFile = serde_json::from_value(value).map_err(|e| e.to_string())?;
+
+    let registry = registry();
+    let levels = save
+        .levels
+        .into_iter()
+        .map(|stored| stored.map(|level| load_level(&registry, level)).transpose())
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut world = World::default();
+    let mut resources = Resources::default();
+    enter_level(
+        load_level(&registry, save.current)?,
+        &mut world,
+        &mut resources,
+    );
+    resources.insert(Dungeon::restore(save.depth, levels));
+    resources.insert(GameLog { entries: save.log });
+    insert_turn_resources(&mut resources, &settings);
+
+    let player = <Entity>::query()
+        .filter(component::<IsPlayer>())
+        .iter(&world)
+        .next()
+        .copied()
+        .ok_or("there is no player in the save")?;
+    fastrand::seed(save.rng_state);
+    Ok(Simulation {
+        world,
+        resources,
+        schedule: turn_schedule(),
+        settings,
+        player,
+        turn: save.turn,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::PlayerCommand;
+    use crate::simulation::tests::{depth, find_tile, new_simulation, snapshot, teleport, SEED};
+    use crate::simulation::TURN_DELTA;
+
+    /// What the player has and where the game is, compared after loading.
+    type PlayerState = (
+        Option<Position>,
+        Purse,
+        Vec<String>,
+        usize,
+        Vec<bool>,
+        u64,
+        Vec<String>,
+    );
+
+    fn player_state(sim: &Simulation) -> PlayerState {
+        let purse = <&Purse>::query()
+            .filter(component::<IsPlayer>())
+            .iter(&sim.world)
+            .next()
+            .copied()
+            .expect("player purse");
+        let mut backpack: Vec<String> = <(&Item, &InBackpack)>::query()
+            .iter(&sim.world)
+            .filter(|(_, pack)| pack.owner == sim.player)
+            .map(|(item, _)| item.name.clone())
+            .collect();
+        backpack.sort();
+        let stored = sim
+            .resources
+            .get::<Dungeon>()
+            .expect("dungeon")
+            .levels()
+            .iter()
+            .map(Option::is_some)
+            .collect();
+        let log = sim.resources.get::<GameLog>().expect("log").entries.clone();
+        (
+            sim.player_position(),
+            purse,
+            backpack,
+            depth(sim),
+            stored,
+            sim.turn,
+            log,
+        )
+    }
+
+    /// Take the stairs down twice, the second level below is generated with the random generator.
+    fn go_down(sim: &mut Simulation) {
+        for _ in 0..2 {
+            let stairs = find_tile(sim, Tile::STAIRS_DOWN).expect("stairs down");
+            teleport(sim, stairs);
+            sim.step(Some(PlayerCommand::TakeStairs), TURN_DELTA);
+        }
+        sim.step(Some(PlayerCommand::Wait), TURN_DELTA);
+    }
+
+    #[test]
+    fn save_and_load_mid_run() {
+        let mut sim = new_simulation(SEED);
+        // Visit the level below so there is a stored level.
+        go_down(&mut sim);
+        let stairs = find_tile(&sim, Tile::STAIRS_UP).expect("stairs up");
+        teleport(&mut sim, stairs);
+        sim.step(Some(PlayerCommand::TakeStairs), TURN_DELTA);
+        sim.world.push((
+            Item {
+                name: "a test potion".to_string(),
+                description: String::new(),
+            },
+            InBackpack { owner: sim.player },
+        ));
+        if let Some(mut entry) = sim.world.entry(sim.player) {
+            entry
+                .get_component_mut::<Purse>()
+                .expect("player purse")
+                .gold = 7;
+        }
+
+        let file = std::env::temp_dir().join(format!("roguelike-save-{}.json", std::process::id()));
+        let path = file.to_str().expect("temporary path");
+        save_game(&sim, path).expect("saved game");
+        let saved_state = player_state(&sim);
+        go_down(&mut sim);
+
+        let mut loaded = load_game(path, Settings::default()).expect("loaded game");
+        fs::remove_file(path).ok();
+        assert_eq!(player_state(&loaded), saved_state);
+        // The random generator goes on where it was saved, so the game goes on the same way.
+        go_down(&mut loaded);
+        assert_eq!(snapshot(&loaded), snapshot(&sim));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::characters::monster::IsMonster;
+use crate::characters::player::{IsPlayer, MoveRules, PlayerTurn, Travel, Viewshed};
+use crate::input::{PlayerCommand, PlayerInput};
+use crate::items::effects::Confused;
+use crate::items::spill_chest;
+use crate::map::doors::{bump_door, DoorBump, TileChanges};
+use crate::map::pathfinding::{cuts_wall_corner, find_path};
+use crate::map::tiles::{Position, Tile, DIRECTIONS};
+use crate::utils::gamelog::GameLog;
+use fastrand::Rng;
+use legion::{component, system, systems::CommandBuffer, world::SubWorld, Entity, IntoQuery};
+use macroquad::debug;
+use std::cmp::max;
+use std::collections::HashSet;
+
+/// Calculate the viewshed.
+#[system(for_each)]
+pub fn update_viewshed(
+    viewshed: &mut Viewshed,
+    origin: &Position,
+    _: &IsPlayer,
+    #[resource] map: &Vec<Vec<Tile>>,
+    #[resource] revealed_tiles: &mut Vec<Vec<bool>>,
+) {
+    use symmetric_shadowcasting::compute_fov;
+    if viewshed.dirty {
+        viewshed.visible_tiles.clear();
+        let mut is_blocking = |pos: (isize, isize)| {
+            let outside = (pos.1 as usize) >= map.len() || (pos.0 as usize) >= map[0].len();
+            outside || map[pos.0 as usize][pos.1 as usize].is_opaque()
+        };
+
+        let mut mark_visible = |pos: (isize, isize)| {
+            let outside = (pos.1 as usize) >= map.len() || (pos.0 as usize) >= map[0].len();
+            let in_range = (pos.1 as usize) >= max(origin.y - viewshed.range, 0) as usize
+                && (pos.1 as usize) <= (origin.y + viewshed.range) as usize
+                && (pos.0 as usize) >= max(origin.x - viewshed.range, 0) as usize
+                && (pos.0 as usize) <= (origin.x + viewshed.range) as usize;
+            let tile_pos = Position {
+                x: pos.0 as i32,
+                y: pos.1 as i32,
+            };
+            if in_range && !outside && !viewshed.visible_tiles.contains(&tile_pos) {
+                viewshed.visible_tiles.push(tile_pos);
+                revealed_tiles[tile_pos.x as usize][tile_pos.y as usize] = true;
+            }
+        };
+
+        let (ox, oy) = origin.as_tuple();
+        let orig = (ox as isize, oy as isize);
+        compute_fov(orig, &mut is_blocking, &mut mark_visible);
+        viewshed.dirty = false;
+    }
+}
+
+/// Make every entity that could see a changed tile recalculate its viewshed.
+#[system]
+#[read_component(Position)]
+#[write_component(Viewshed)]
+pub fn mark_viewsheds_dirty(world: &mut SubWorld, #[resource] changes: &mut TileChanges) {
+    if changes.tiles.is_empty() {
+        return;
+    }
+    for (pos, viewshed) in <(&Position, &mut Viewshed)>::query().iter_mut(world) {
+        let nearby = changes.tiles.iter().any(|tile| {
+            (tile.x - pos.x).abs() <= viewshed.range && (tile.y - pos.y).abs() <= viewshed.range
+        });
+        if nearby {
+            viewshed.dirty = true;
+        }
+    }
+    changes.tiles.clear();
+}
+
+/// Handle the move and wait commands. Try to move the player in one of 8 directions
+/// (handles collisions) or wait a turn. Bumping into a chest or a door opens it.
+/// A confused player stumbles in a random direction instead.
+#[system(for_each)]
+#[filter(component::<IsPlayer>())]
+#[allow(clippy::too_many_arguments)]
+pub fn handle_command(
+    current_pos: &mut Position,
+    viewshed: &mut Viewshed,
+    confused: Option<&Confused>,
+    cmd: &mut CommandBuffer,
+    #[resource] map: &mut Vec<Vec<Tile>>,
+    #[resource] travel: &mut Travel,
+    #[resource] log: &mut GameLog,
+    #[resource] changes: &mut TileChanges,
+    #[resource] input: &PlayerInput,
+    #[resource] rules: &MoveRules,
+) {
+    let (dx, dy) = match input.command {
+        Some(PlayerCommand::Move { .. }) if confused.is_some() => {
+            log.add("You stumble around in confusion.".to_string());
+            DIRECTIONS[Rng::new().usize(..DIRECTIONS.len())]
+        }
+        Some(PlayerCommand::Move { dx, dy }) => (dx, dy),
+        Some(PlayerCommand::Wait) => {
+            log.add("You wait.".to_string());
+            travel.cancel();
+            return;
+        }
+        _ => return,
+    };
+    // Saves the current position in case the destination is not walkable.
+    let pos = Position {
+        x: current_pos.x + dx,
+        y: current_pos.y + dy,
+    };
+    if !rules.corner_cutting && cuts_wall_corner(map, *current_pos, pos) {
+        log.add("You can't squeeze past the corner.".to_string());
+        return;
+    }
+
+    if let Some(bump) = bump_door(map, pos, changes) {
+        let message = match bump {
+            DoorBump::Opened => "You open the door.",
+            DoorBump::PickedLock => "You pick the lock.",
+            DoorBump::StillLocked => "The door is locked, you fiddle with the lock.",
+        };
+        log.add(message.to_string());
+        travel.cancel();
+        return;
+    }
+
+    // Resets the position if the destination is not walkable.
+    // Prints coords of out-of-bounds entities.
+    if let Some(row) = map.get(pos.x as usize) {
+        if let Some(tile) = row.get(pos.y as usize) {
+            if *tile == Tile::CHEST {
+                let loot_count = spill_chest(cmd, map, pos);
+                changes.add(pos);
+                log.add(format!("You open the chest, {loot_count} items fall out."));
+                viewshed.dirty = true;
+                travel.cancel();
+                return;
+            }
+            if tile.is_walkable() && pos != *current_pos {
+                current_pos.x = pos.x;
+                current_pos.y = pos.y;
+                viewshed.dirty = true;
+                // Manual movement interrupts the click-to-travel.
+                travel.cancel();
+            }
+        }
+    }
+}
+
+/// Seconds between two steps of the click-to-travel.
+const TRAVEL_STEP_DELAY: f32 = 0.1;
+
+/// Plan the path to the travel destination and walk it one step per turn.
+/// A `TravelTo` command sets a new destination.
+/// Stops when a monster that was not in view before comes into view.
+#[system]
+#[write_component(Position)]
+#[write_component(Viewshed)]
+#[read_component(IsPlayer)]
+#[read_component(IsMonster)]
+#[read_component(Confused)]
+#[allow(clippy::too_many_arguments)]
+pub fn travel(
+    world: &mut SubWorld,
+    #[resource] map: &mut Vec<Vec<Tile>>,
+    #[resource] revealed_tiles: &Vec<Vec<bool>>,
+    #[resource] travel: &mut Travel,
+    #[resource] changes: &mut TileChanges,
+    #[resource] input: &PlayerInput,
+    #[resource] rules: &MoveRules,
+    #[resource] turn: &mut PlayerTurn,
+    #[resource] log: &mut GameLog,
+) {
+    let confused = <&Confused>::query()
+        .filter(component::<IsPlayer>())
+        .iter(world)
+        .next()
+        .is_some();
+    if let Some(PlayerCommand::TravelTo(destination)) = input.command {
+        debug!("Travel to x:{} , y:{}", destination.x, destination.y);
+        // A new destination replaces the planned path.
+        travel.cancel();
+        if confused {
+            log.add("You are too confused to find the way.".to_string());
+            return;
+        }
+        travel.destination = Some(destination);
+    }
+    let Some(destination) = travel.destination else {
+        return;
+    };
+    let monsters: Vec<(Entity, Position)> = <(Entity, &Position)>::query()
+        .filter(component::<IsMonster>())
+        .iter(world)
+        .map(|(entity, pos)| (*entity, *pos))
+        .collect();
+    let mut query = <(&mut Position, &mut Viewshed)>::query().filter(component::<IsPlayer>());
+    for (pos, viewshed) in query.iter_mut(world) {
+        let monsters_in_view: HashSet<Entity> = monsters
+            .iter()
+            .filter(|(_, monster)| viewshed.visible_tiles.contains(monster))
+            .map(|(entity, _)| *entity)
+            .collect();
+
+        if travel.path.is_empty() {
+            match find_path(map, revealed_tiles, *pos, destination, rules.corner_cutting) {
+                Some(path) if !path.is_empty() => {
+                    travel.path = path;
+                    travel.cooldown = 0.0;
+                    travel.monsters_in_view = monsters_in_view;
+                }
+                _ => travel.cancel(),
+            }
+            continue;
+        }
+
+        travel.cooldown -= input.delta;
+        if travel.cooldown > 0.0 {
+            continue;
+        }
+        if !monsters_in_view.is_subset(&travel.monsters_in_view) {
+            debug!("Travel interrupted: a monster came into view");
+            travel.cancel();
+            continue;
+        }
+        travel.monsters_in_view = monsters_in_view;
+
+        // Closed doors on the way take a turn to open.
+        if bump_door(map, travel.path[0], changes) == Some(DoorBump::Opened) {
+            travel.cooldown = TRAVEL_STEP_DELAY;
+            turn.taken = true;
+            continue;
+        }
+        let step = travel.path.remove(0);
+        let walkable = map
+            .get(step.x as usize)
+            .and_then(|row| row.get(step.y as usize))
+            .is_some_and(|tile| tile.is_walkable());
+        if !walkable {
+            travel.cancel();
+            continue;
+        }
+        *pos = step;
+        viewshed.dirty = true;
+        travel.cooldown = TRAVEL_STEP_DELAY;
+        turn.taken = true;
+        if travel.path.is_empty() {
+            travel.cancel();
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::map::generators::Map;
+use crate::map::tiles::Position;
+use crate::raws::raws;
+use fastrand::Rng;
+use legion::systems::CommandBuffer;
+
+/// Fill the level with monsters and items from the spawn table of the raws. Every room gets a few spawns,
+/// more of them on deeper levels. Maps without rooms get the same amount on random tiles.
+pub fn populate_level(cmd: &mut CommandBuffer, map: &Map, depth: usize) {
+    const SPAWNS_PER_ROOM: usize = 2;
+    const TILES_PER_ROOM: usize = 100;
+    let rng = Rng::new();
+    let max_spawns = SPAWNS_PER_ROOM + depth;
+
+    let mut spawn_points: Vec<Position> = Vec::new();
+    match &map.rooms {
+        Some(rooms) if !rooms.is_empty() => {
+            for room in rooms {
+                let floors = room.get_floors_positions();
+                for _ in 0..rng.usize(..=max_spawns) {
+                    let (x, y) = floors[rng.usize(..floors.len())];
+                    spawn_points.push(Position {
+                        x: x as i32,
+                        y: y as i32,
+                    });
+                }
+            }
+        }
+        _ => {
+            let room_count = map.tiles.len() * map.tiles[0].len() / TILES_PER_ROOM;
+            for _ in 0..room_count * max_spawns / 2 {
+                if let Some(pos) = map.random_walkable_position(&rng) {
+                    spawn_points.push(pos);
+                }
+            }
+        }
+    }
+
+    spawn_points.sort_by_key(|pos| (pos.x, pos.y));
+    spawn_points.dedup();
+    for pos in spawn_points {
+        let walkable = map.tiles[pos.x as usize][pos.y as usize].is_walkable();
+        if !walkable || pos == map.entrance {
+            continue;
+        }
+        if let Some(name) = raws().roll_spawn(depth, &rng) {
+            raws().spawn_named(cmd, name, pos);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use macroquad::error;
+use std::env;
+
+/// Default file of `--record` without a path.
+const RECORD_PATH: &str = "recording.jsonl";
+/// Default file of `--load` without a path, the game is saved there on quit.
+const SAVE_PATH: &str = "savegame.json";
+
+/// Options given on the command line.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Args {
+    /// `--seed <number>`: seed of the random generator.
+    pub seed: Option<u64>,
+    /// `--record [file]`: record the run to be replayed later.
+    pub record: Option<String>,
+    /// `--replay <file>`: replay a recorded run, the player takes over once it ends.
+    pub replay: Option<String>,
+    /// `--load [file]`: go on with a saved game.
+    pub load: Option<String>,
+    /// `--headless`: run without a window, see `run_headless()`.
+    pub headless: bool,
+    /// `--ticks <number>`: ticks without commands a headless run steps after the replay,
+    /// e.g. to let a travel finish.
+    pub ticks: usize,
+}
+
+impl Args {
+    /// Parse the command line of the game, unknown options are ignored.
+    pub fn parse() -> Self {
+        let mut args = Self::default();
+        let mut iter = env::args().skip(1).peekable();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    args.seed = iter.next().and_then(|value| value.parse::<u64>().ok());
+                    if args.seed.is_none() {
+                        error!("--seed needs a number");
+                    }
+                }
+                "--record" => {
+                    let path = iter.next_if(|value| !value.starts_with("--"));
+                    args.record = Some(path.unwrap_or_else(|| RECORD_PATH.to_string()));
+                }
+                "--replay" => {
+                    args.replay = iter.next();
+                    if args.replay.is_none() {
+                        error!("--replay needs a file");
+                    }
+                }
+                "--load" => {
+                    let path = iter.next_if(|value| !value.starts_with("--"));
+                    args.load = Some(path.unwrap_or_else(|| SAVE_PATH.to_string()));
+                }
+                "--headless" => args.headless = true,
+                "--ticks" => {
+                    let ticks = iter.next().and_then(|value| value.parse::<usize>().ok());
+                    if ticks.is_none() {
+                        error!("--ticks needs a number");
+                    }
+                    args.ticks = ticks.unwrap_or_default();
+                }
+                _ => error!("unknown option {}", arg),
+            }
+        }
+        args
+    }
+
+    /// The file the game is saved to on quit, the loaded save is overwritten.
+    pub fn save_path(&self) -> &str {
+        self.load.as_deref().unwrap_or(SAVE_PATH)
+    }
+}
@@ -1,30 +1,147 @@
-use macroquad::{is_key_down, mouse_position, screen_height, screen_width, vec2, KeyCode, Vec2};
+use crate::map::tiles::Position;
+use crate::utils::keymap::{Action, Keymap};
+use macroquad::{
+    is_mouse_button_down, mouse_position, mouse_wheel, screen_height, screen_width, vec2, Camera2D,
+    MouseButton, Vec2,
+};
+
+/// Seconds the camera stops following the player after a manual scroll.
+const MANUAL_OVERRIDE_TIME: f32 = 2.0;
+/// Part of the view around the center the player can move in without moving the camera.
+const DEADZONE: f32 = 0.4;
+/// How fast the camera catches up with the player in the deadzone mode.
+const SMOOTHING: f32 = 8.0;
+/// The furthest the camera can zoom out.
+const MIN_ZOOM: f32 = 0.01;
+/// The closest the camera can zoom in.
+const MAX_ZOOM: f32 = 0.5;
+/// Zoom change of one mouse wheel step.
+const WHEEL_ZOOM: f32 = 1.1;
+/// How far the camera center can go past the map edges, in tiles.
+const BOUNDS_MARGIN: f32 = 5.0;
+
+/// How the camera follows the player.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FollowMode {
+    /// The player is always in the center.
+    Locked,
+    /// The camera smoothly moves once the player leaves the deadzone.
+    #[default]
+    Deadzone,
+    /// The camera moves only with the manual scroll.
+    Free,
+}
+
+impl FollowMode {
+    /// Get the next mode, used to cycle through them.
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Locked => Self::Deadzone,
+            Self::Deadzone => Self::Free,
+            Self::Free => Self::Locked,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Locked => "locked",
+            Self::Deadzone => "deadzone",
+            Self::Free => "free",
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Camera {
     target: Vec2,
-    zoom: Vec2,
+    /// Horizontal zoom, the vertical one follows the window aspect ratio.
+    zoom: f32,
+    pub mode: FollowMode,
+    /// Seconds left until the camera follows the player again after a manual scroll.
+    manual_override: f32,
+    /// World point held by the mouse while dragging, it stays under the cursor.
+    drag_anchor: Option<Vec2>,
 }
 
 impl Camera {
-    pub const fn get(&self) -> (Vec2, Vec2) {
-        (self.target, self.zoom)
-    }
-
     pub fn set_target(&mut self, new_target: Vec2) {
         self.target = new_target;
     }
+
+    /// Multiply the zoom by `factor`, kept between the zoom limits.
+    fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    /// Get the zoom on both axes for the current window size, so resizing doesn't
+    /// stretch the view.
+    fn zoom2d(&self) -> Vec2 {
+        vec2(self.zoom, self.zoom * screen_width() / screen_height())
+    }
+
+    /// Keep the camera center over the map of the given size, with a small margin.
+    pub fn clamp_to_map(&mut self, width: usize, height: usize) {
+        self.target = vec2(
+            self.target
+                .x()
+                .clamp(-BOUNDS_MARGIN, width as f32 + BOUNDS_MARGIN),
+            self.target
+                .y()
+                .clamp(-BOUNDS_MARGIN, height as f32 + BOUNDS_MARGIN),
+        );
+    }
+
+    /// Move the camera after the player at `player` according to the follow mode.
+    /// `delta` is the frame time in seconds.
+    pub fn follow(&mut self, player: Position, delta: f32) {
+        if self.manual_override > 0.0 {
+            self.manual_override -= delta;
+            return;
+        }
+        let player_center = vec2(player.x as f32 + 0.5, player.y as f32 + 0.5);
+        match self.mode {
+            FollowMode::Locked => self.target = player_center,
+            FollowMode::Deadzone => {
+                let zoom = self.zoom2d();
+                let deadzone = vec2(DEADZONE / zoom.x(), DEADZONE / zoom.y());
+                let offset = player_center - self.target;
+                let desired = self.target + offset - offset.max(-deadzone).min(deadzone);
+                let step = (SMOOTHING * delta).min(1.0);
+                self.target += (desired - self.target) * step;
+            }
+            FollowMode::Free => {}
+        }
+    }
+
+    /// Get the macroquad camera to draw the world with.
+    pub fn camera2d(&self) -> Camera2D {
+        Camera2D {
+            target: self.target,
+            zoom: self.zoom2d(),
+            ..Camera2D::default()
+        }
+    }
+
+    /// Get the bottom left and the top right tiles the camera can see.
+    pub fn visible_bounds(&self) -> (Position, Position) {
+        // The camera shows `1 / zoom` world units on each side of the target.
+        let zoom = self.zoom2d();
+        let half_size = vec2(1.0 / zoom.x(), 1.0 / zoom.y());
+        (
+            Position::from(self.target - half_size),
+            Position::from(self.target + half_size),
+        )
+    }
 }
 
 impl Default for Camera {
     fn default() -> Self {
-        let starting_zoom = 0.10;
         Self {
             target: vec2(0.0, 0.0),
-            zoom: vec2(
-                starting_zoom,
-                starting_zoom * screen_width() / screen_height(),
-            ),
+            zoom: 0.10,
+            mode: FollowMode::default(),
+            manual_override: 0.0,
+            drag_anchor: None,
         }
     }
 }
@@ -33,54 +150,86 @@ impl Default for Camera {
 pub fn relative_mouse_position(camera: &Camera) -> Vec2 {
     // Takes the mouse coordinates on window and translates that
     // to game world coordinates.
+    // The window size is read every time, so it stays correct after resizing.
     let mouse = mouse_position();
+    let zoom = camera.zoom2d();
     Vec2::new(
-        ((mouse.0 - screen_width() / 2.0) / (screen_width() / 2.0) / camera.zoom.x())
-            + camera.target.x(),
-        ((-mouse.1 + screen_height() / 2.0)
-            / (screen_height() / 2.0)
-            / camera.zoom.x()
-            / (screen_width() / screen_height()))
+        ((mouse.0 - screen_width() / 2.0) / (screen_width() / 2.0) / zoom.x()) + camera.target.x(),
+        ((-mouse.1 + screen_height() / 2.0) / (screen_height() / 2.0) / zoom.y())
             + camera.target.y(),
     )
 }
 
+/// Zoom with the mouse wheel around the cursor and pan by dragging
+/// with the middle or the right mouse button.
+fn mouse_control(camera: &mut Camera) {
+    let (_, wheel) = mouse_wheel();
+    if wheel != 0.0 {
+        // Keep the world point under the cursor in place.
+        let before = relative_mouse_position(camera);
+        camera.zoom_by(if wheel > 0.0 {
+            WHEEL_ZOOM
+        } else {
+            1.0 / WHEEL_ZOOM
+        });
+        camera.target += before - relative_mouse_position(camera);
+    }
+
+    if is_mouse_button_down(MouseButton::Middle) || is_mouse_button_down(MouseButton::Right) {
+        let mouse = relative_mouse_position(camera);
+        let anchor = *camera.drag_anchor.get_or_insert(mouse);
+        camera.target += anchor - mouse;
+        camera.manual_override = MANUAL_OVERRIDE_TIME;
+    } else {
+        camera.drag_anchor = None;
+    }
+}
+
 /// Get and handle the input related to the camera.
-pub fn scroll(camera: &mut Camera, scroll_speed: f32, zoom_speed: f32) {
+/// Scrolling stops the camera from following the player for a while.
+pub fn scroll(camera: &mut Camera, keymap: &Keymap, scroll_speed: f32, zoom_speed: f32) {
+    mouse_control(camera);
+    let scroll_actions = [
+        Action::CameraUp,
+        Action::CameraDown,
+        Action::CameraLeft,
+        Action::CameraRight,
+    ];
+    if scroll_actions.iter().any(|action| keymap.down(*action)) {
+        camera.manual_override = MANUAL_OVERRIDE_TIME;
+    }
     // Move the camera:
     // UP
-    if is_key_down(KeyCode::Comma) {
+    if keymap.down(Action::CameraUp) {
         camera
             .target
-            .set_y(camera.target.y() + scroll_speed / camera.zoom.x())
+            .set_y(camera.target.y() + scroll_speed / camera.zoom)
     }
     // DOWN
-    if is_key_down(KeyCode::O) {
+    if keymap.down(Action::CameraDown) {
         camera
             .target
-            .set_y(camera.target.y() - scroll_speed / camera.zoom.x())
+            .set_y(camera.target.y() - scroll_speed / camera.zoom)
     }
     // LEFT
-    if is_key_down(KeyCode::A) {
+    if keymap.down(Action::CameraLeft) {
         camera
             .target
-            .set_x(camera.target.x() - scroll_speed / camera.zoom.x())
+            .set_x(camera.target.x() - scroll_speed / camera.zoom)
     }
     // RIGHT
-    if is_key_down(KeyCode::E) {
+    if keymap.down(Action::CameraRight) {
         camera
             .target
-            .set_x(camera.target.x() + scroll_speed / camera.zoom.x())
+            .set_x(camera.target.x() + scroll_speed / camera.zoom)
     }
     // Change the camera zoom:
     // Further
-    if is_key_down(KeyCode::Apostrophe) {
-        camera.zoom.set_x(camera.zoom.x() * zoom_speed);
-        camera.zoom.set_y(camera.zoom.y() * zoom_speed);
+    if keymap.down(Action::ZoomOut) {
+        camera.zoom_by(zoom_speed);
     }
     // Closer
-    if is_key_down(KeyCode::Period) {
-        camera.zoom.set_x(camera.zoom.x() / zoom_speed);
-        camera.zoom.set_y(camera.zoom.y() / zoom_speed);
+    if keymap.down(Action::ZoomIn) {
+        camera.zoom_by(1.0 / zoom_speed);
     }
 }
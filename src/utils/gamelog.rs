@@ -0,0 +1,17 @@
+/// Messages about what happened in the game, newest last.
+#[derive(Clone, Debug, Default)]
+pub struct GameLog {
+    pub entries: Vec<String>,
+}
+
+impl GameLog {
+    pub fn add(&mut self, message: String) {
+        self.entries.push(message);
+    }
+
+    /// Get up to `count` of the newest messages, oldest first.
+    pub fn latest(&self, count: usize) -> &[String] {
+        let start = self.entries.len().saturating_sub(count);
+        &self.entries[start..]
+    }
+}
@@ -0,0 +1,378 @@
+use crate::map::tiles::DIRECTIONS;
+use macroquad::{is_key_down, is_key_pressed, KeyCode};
+use std::collections::HashMap;
+
+/// Something the player can do with a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveN,
+    MoveNE,
+    MoveE,
+    MoveSE,
+    MoveS,
+    MoveSW,
+    MoveW,
+    MoveNW,
+    Wait,
+    Pickup,
+    Inventory,
+    TakeStairs,
+    CameraUp,
+    CameraDown,
+    CameraLeft,
+    CameraRight,
+    ZoomIn,
+    ZoomOut,
+    CameraMode,
+    /// Accept the choice, e.g. the target of a ranged item.
+    Confirm,
+    /// Save the game and close the window.
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Self; 21] = [
+        Self::MoveN,
+        Self::MoveNE,
+        Self::MoveE,
+        Self::MoveSE,
+        Self::MoveS,
+        Self::MoveSW,
+        Self::MoveW,
+        Self::MoveNW,
+        Self::Wait,
+        Self::Pickup,
+        Self::Inventory,
+        Self::TakeStairs,
+        Self::CameraUp,
+        Self::CameraDown,
+        Self::CameraLeft,
+        Self::CameraRight,
+        Self::ZoomIn,
+        Self::ZoomOut,
+        Self::CameraMode,
+        Self::Confirm,
+        Self::Quit,
+    ];
+
+    /// Name of the action in the settings file, e.g. `key.move_n=Up`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::MoveN => "move_n",
+            Self::MoveNE => "move_ne",
+            Self::MoveE => "move_e",
+            Self::MoveSE => "move_se",
+            Self::MoveS => "move_s",
+            Self::MoveSW => "move_sw",
+            Self::MoveW => "move_w",
+            Self::MoveNW => "move_nw",
+            Self::Wait => "wait",
+            Self::Pickup => "pickup",
+            Self::Inventory => "inventory",
+            Self::TakeStairs => "take_stairs",
+            Self::CameraUp => "camera_up",
+            Self::CameraDown => "camera_down",
+            Self::CameraLeft => "camera_left",
+            Self::CameraRight => "camera_right",
+            Self::ZoomIn => "zoom_in",
+            Self::ZoomOut => "zoom_out",
+            Self::CameraMode => "camera_mode",
+            Self::Confirm => "confirm",
+            Self::Quit => "quit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+    }
+
+    /// Get the step of a move action, (x, y) with y up.
+    pub const fn direction(self) -> Option<(i32, i32)> {
+        match self {
+            Self::MoveN => Some(DIRECTIONS[0]),
+            Self::MoveNE => Some(DIRECTIONS[1]),
+            Self::MoveE => Some(DIRECTIONS[2]),
+            Self::MoveSE => Some(DIRECTIONS[3]),
+            Self::MoveS => Some(DIRECTIONS[4]),
+            Self::MoveSW => Some(DIRECTIONS[5]),
+            Self::MoveW => Some(DIRECTIONS[6]),
+            Self::MoveNW => Some(DIRECTIONS[7]),
+            Self::Wait
+            | Self::Pickup
+            | Self::Inventory
+            | Self::TakeStairs
+            | Self::CameraUp
+            | Self::CameraDown
+            | Self::CameraLeft
+            | Self::CameraRight
+            | Self::ZoomIn
+            | Self::ZoomOut
+            | Self::CameraMode
+            | Self::Confirm
+            | Self::Quit => None,
+        }
+    }
+}
+
+/// Names of the keys in the settings file and in the hints.
+const KEY_NAMES: [(KeyCode, &str); 67] = [
+    (KeyCode::A, "a"),
+    (KeyCode::B, "b"),
+    (KeyCode::C, "c"),
+    (KeyCode::D, "d"),
+    (KeyCode::E, "e"),
+    (KeyCode::F, "f"),
+    (KeyCode::G, "g"),
+    (KeyCode::H, "h"),
+    (KeyCode::I, "i"),
+    (KeyCode::J, "j"),
+    (KeyCode::K, "k"),
+    (KeyCode::L, "l"),
+    (KeyCode::M, "m"),
+    (KeyCode::N, "n"),
+    (KeyCode::O, "o"),
+    (KeyCode::P, "p"),
+    (KeyCode::Q, "q"),
+    (KeyCode::R, "r"),
+    (KeyCode::S, "s"),
+    (KeyCode::T, "t"),
+    (KeyCode::U, "u"),
+    (KeyCode::V, "v"),
+    (KeyCode::W, "w"),
+    (KeyCode::X, "x"),
+    (KeyCode::Y, "y"),
+    (KeyCode::Z, "z"),
+    (KeyCode::Key0, "0"),
+    (KeyCode::Key1, "1"),
+    (KeyCode::Key2, "2"),
+    (KeyCode::Key3, "3"),
+    (KeyCode::Key4, "4"),
+    (KeyCode::Key5, "5"),
+    (KeyCode::Key6, "6"),
+    (KeyCode::Key7, "7"),
+    (KeyCode::Key8, "8"),
+    (KeyCode::Key9, "9"),
+    (KeyCode::Kp0, "kp0"),
+    (KeyCode::Kp1, "kp1"),
+    (KeyCode::Kp2, "kp2"),
+    (KeyCode::Kp3, "kp3"),
+    (KeyCode::Kp4, "kp4"),
+    (KeyCode::Kp5, "kp5"),
+    (KeyCode::Kp6, "kp6"),
+    (KeyCode::Kp7, "kp7"),
+    (KeyCode::Kp8, "kp8"),
+    (KeyCode::Kp9, "kp9"),
+    (KeyCode::Up, "up"),
+    (KeyCode::Down, "down"),
+    (KeyCode::Left, "left"),
+    (KeyCode::Right, "right"),
+    (KeyCode::Space, "space"),
+    (KeyCode::Enter, "enter"),
+    (KeyCode::Tab, "tab"),
+    (KeyCode::Escape, "escape"),
+    (KeyCode::Backspace, "backspace"),
+    (KeyCode::Apostrophe, "'"),
+    (KeyCode::Comma, ","),
+    (KeyCode::Period, "."),
+    (KeyCode::Slash, "/"),
+    (KeyCode::Semicolon, ";"),
+    (KeyCode::Minus, "-"),
+    (KeyCode::Equal, "="),
+    (KeyCode::LeftBracket, "["),
+    (KeyCode::RightBracket, "]"),
+    (KeyCode::Backslash, "\\"),
+    (KeyCode::KpAdd, "kp+"),
+    (KeyCode::KpSubtract, "kp-"),
+];
+
+/// Find the key by its name, e.g. `up` or `g`.
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    let lowercase = name.to_lowercase();
+    KEY_NAMES
+        .iter()
+        .find(|(_, key_name)| *key_name == lowercase)
+        .map(|(key, _)| *key)
+}
+
+pub fn key_name(key: KeyCode) -> &'static str {
+    KEY_NAMES
+        .iter()
+        .find(|(code, _)| *code == key)
+        .map_or("?", |(_, name)| name)
+}
+
+/// Lines of the ui hints: the actions, the first key of each is shown, and the text after them.
+const HINTS: [(&[Action], &str); 9] = [
+    (
+        &[
+            Action::CameraUp,
+            Action::CameraLeft,
+            Action::CameraDown,
+            Action::CameraRight,
+        ],
+        "to move camera",
+    ),
+    (
+        &[Action::ZoomIn, Action::ZoomOut],
+        "or the wheel to zoom, drag to pan",
+    ),
+    (
+        &[
+            Action::MoveN,
+            Action::MoveW,
+            Action::MoveS,
+            Action::MoveE,
+            Action::MoveNW,
+            Action::MoveNE,
+            Action::MoveSW,
+            Action::MoveSE,
+        ],
+        "to move the player",
+    ),
+    (&[Action::Wait], "to wait"),
+    (&[Action::Pickup], "to pick up items"),
+    (&[Action::Inventory], "to open the inventory"),
+    (&[Action::TakeStairs], "to take the stairs"),
+    (&[Action::CameraMode], "to change the camera mode"),
+    (&[Action::Quit], "to save and quit"),
+];
+
+/// Keys bound to the actions, an action can have several keys.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<Action, Vec<KeyCode>>,
+}
+
+impl Keymap {
+    /// Get one of the built in keymaps: `qwerty`, `dvorak` or `vi`.
+    pub fn preset(name: &str) -> Option<Self> {
+        let common: [(Action, &[KeyCode]); 15] = [
+            (Action::MoveN, &[KeyCode::Kp8]),
+            (Action::MoveNE, &[KeyCode::Kp9]),
+            (Action::MoveE, &[KeyCode::Kp6]),
+            (Action::MoveSE, &[KeyCode::Kp3]),
+            (Action::MoveS, &[KeyCode::Kp2]),
+            (Action::MoveSW, &[KeyCode::Kp1]),
+            (Action::MoveW, &[KeyCode::Kp4]),
+            (Action::MoveNW, &[KeyCode::Kp7]),
+            (Action::Wait, &[KeyCode::Kp5]),
+            (Action::Pickup, &[KeyCode::G]),
+            (Action::Inventory, &[KeyCode::I]),
+            (Action::TakeStairs, &[KeyCode::Enter]),
+            (Action::CameraMode, &[KeyCode::C]),
+            (Action::Confirm, &[KeyCode::Enter]),
+            (Action::Quit, &[KeyCode::Q]),
+        ];
+        let arrows: [(Action, &[KeyCode]); 4] = [
+            (Action::MoveN, &[KeyCode::Up]),
+            (Action::MoveE, &[KeyCode::Right]),
+            (Action::MoveS, &[KeyCode::Down]),
+            (Action::MoveW, &[KeyCode::Left]),
+        ];
+        let specific: Vec<(Action, &[KeyCode])> = match name {
+            "qwerty" => [
+                (Action::CameraUp, &[KeyCode::W] as &[KeyCode]),
+                (Action::CameraDown, &[KeyCode::S]),
+                (Action::CameraLeft, &[KeyCode::A]),
+                (Action::CameraRight, &[KeyCode::D]),
+                (Action::ZoomIn, &[KeyCode::Equal]),
+                (Action::ZoomOut, &[KeyCode::Minus]),
+                (Action::Wait, &[KeyCode::Space]),
+            ]
+            .iter()
+            .chain(arrows.iter())
+            .copied()
+            .collect(),
+            "dvorak" => [
+                (Action::CameraUp, &[KeyCode::Comma] as &[KeyCode]),
+                (Action::CameraDown, &[KeyCode::O]),
+                (Action::CameraLeft, &[KeyCode::A]),
+                (Action::CameraRight, &[KeyCode::E]),
+                (Action::ZoomIn, &[KeyCode::Period]),
+                (Action::ZoomOut, &[KeyCode::Apostrophe]),
+                (Action::Wait, &[KeyCode::Space]),
+            ]
+            .iter()
+            .chain(arrows.iter())
+            .copied()
+            .collect(),
+            "vi" => vec![
+                (Action::MoveN, &[KeyCode::K] as &[KeyCode]),
+                (Action::MoveE, &[KeyCode::L]),
+                (Action::MoveS, &[KeyCode::J]),
+                (Action::MoveW, &[KeyCode::H]),
+                (Action::MoveNW, &[KeyCode::Y]),
+                (Action::MoveNE, &[KeyCode::U]),
+                (Action::MoveSW, &[KeyCode::B]),
+                (Action::MoveSE, &[KeyCode::N]),
+                (Action::Wait, &[KeyCode::Period]),
+                (Action::CameraUp, &[KeyCode::Up]),
+                (Action::CameraDown, &[KeyCode::Down]),
+                (Action::CameraLeft, &[KeyCode::Left]),
+                (Action::CameraRight, &[KeyCode::Right]),
+                (Action::ZoomIn, &[KeyCode::Equal]),
+                (Action::ZoomOut, &[KeyCode::Minus]),
+            ],
+            _ => return None,
+        };
+        // The preset keys come first, they are the ones shown in the hints.
+        let mut keymap = Self::default();
+        for (action, keys) in specific.iter().chain(common.iter()) {
+            keymap
+                .bindings
+                .entry(*action)
+                .or_default()
+                .extend_from_slice(keys);
+        }
+        Some(keymap)
+    }
+
+    /// Replace the keys of the `action`.
+    pub fn bind(&mut self, action: Action, keys: Vec<KeyCode>) {
+        self.bindings.insert(action, keys);
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Was one of the keys of the `action` pressed this frame.
+    pub fn pressed(&self, action: Action) -> bool {
+        self.keys(action).iter().any(|key| is_key_pressed(*key))
+    }
+
+    /// Get the step of the first move action pressed this frame.
+    pub fn pressed_direction(&self) -> Option<(i32, i32)> {
+        Action::ALL
+            .iter()
+            .filter(|action| self.pressed(**action))
+            .find_map(|action| action.direction())
+    }
+
+    /// Is one of the keys of the `action` held down.
+    pub fn down(&self, action: Action) -> bool {
+        self.keys(action).iter().any(|key| is_key_down(*key))
+    }
+
+    /// Get the ui hint lines for the current bindings, e.g. `g to pick up items`.
+    pub fn hints(&self) -> Vec<String> {
+        HINTS
+            .iter()
+            .filter_map(|(actions, text)| {
+                let keys: Vec<&str> = actions
+                    .iter()
+                    .filter_map(|action| self.keys(*action).first())
+                    .map(|key| key_name(*key))
+                    .collect();
+                if keys.is_empty() {
+                    None
+                } else {
+                    Some(format!("{} {}", keys.join(" "), text))
+                }
+            })
+            .collect()
+    }
+}
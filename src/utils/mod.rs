@@ -1,2 +1,5 @@
+pub mod args;
 pub mod camera;
+pub mod gamelog;
+pub mod keymap;
 pub mod settings;
@@ -1,4 +1,7 @@
-use macroquad::error;
+use crate::map::atlas::RenderMode;
+use crate::utils::camera::FollowMode;
+use crate::utils::keymap::{key_from_name, Action, Keymap};
+use macroquad::{error, KeyCode};
 use std::fs;
 use std::io::Error;
 
@@ -8,6 +11,12 @@ const HEIGHT: usize = 40;
 const GEN_PARAM: i32 = 10;
 const ZOOM_SPEED: f32 = 0.98;
 const SCROLL_SPEED: f32 = 0.02;
+const GENERATOR: &str = "perlin";
+const RENDER_MODE: RenderMode = RenderMode::Tiles;
+const CAMERA_FOLLOW: FollowMode = FollowMode::Deadzone;
+const ATLASES: &str = "assets/Tiles.atlas.json";
+const KEYMAP: &str = "dvorak";
+const CORNER_CUTTING: bool = true;
 
 /// Split the key=value pair into tuple of strings
 fn parse_pair(line: &str) -> (String, String) {
@@ -16,6 +25,32 @@ fn parse_pair(line: &str) -> (String, String) {
     let value = iter.next().expect("parse_pair failed");
     (key.to_string(), value.to_string())
 }
+
+/// Split the comma separated value into a list of strings.
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Parse a `key.<action>=<key>,<key>` pair into the action and its keys.
+fn parse_binding(key: &str, value: &str) -> (Action, Vec<KeyCode>) {
+    let action = Action::from_name(&key["key.".len()..])
+        .unwrap_or_else(|| panic!("Unknown action in key {}!", key));
+    let keys = parse_list(value)
+        .iter()
+        .map(|name| {
+            key_from_name(name)
+                .unwrap_or_else(|| panic!("Cannot parse value {} in key {}!", value, key))
+        })
+        .collect();
+    (action, keys)
+}
+
+#[derive(Clone)]
 struct ConfigFile {
     _path: String,
     vars: Vec<(String, String)>,
@@ -36,6 +71,7 @@ impl ConfigFile {
 }
 
 /// Centralized struct for all customizable variables.
+#[derive(Clone)]
 pub struct Settings {
     _config: Option<ConfigFile>,
     pub width: usize,
@@ -43,6 +79,19 @@ pub struct Settings {
     pub gen_param: i32,
     pub scroll_speed: f32,
     pub zoom_speed: f32,
+    /// Which map generator to use: `perlin` or `rooms`.
+    pub generator: String,
+    /// Draw the tiles as sprites (`tiles`) or as text (`glyphs`).
+    pub render_mode: RenderMode,
+    /// How the camera follows the player: `locked`, `deadzone` or `free`.
+    pub camera_follow: FollowMode,
+    /// Paths to the atlas descriptors of the tilesets, separated by commas in the file.
+    pub atlases: Vec<String>,
+    /// Key bindings: a preset (`qwerty`, `dvorak` or `vi`) from the `keymap` key,
+    /// single actions are rebound with `key.<action>=<key>,<key>`, e.g. `key.pickup=g`.
+    pub keymap: Keymap,
+    /// Allow diagonal steps past the corner of a wall.
+    pub corner_cutting: bool,
 }
 
 impl Settings {
@@ -68,6 +117,13 @@ impl Settings {
         let mut gen_param = GEN_PARAM;
         let mut scroll_speed = SCROLL_SPEED;
         let mut zoom_speed = ZOOM_SPEED;
+        let mut generator = GENERATOR.to_string();
+        let mut render_mode = RENDER_MODE;
+        let mut camera_follow = CAMERA_FOLLOW;
+        let mut atlases = parse_list(ATLASES);
+        let mut keymap_preset = KEYMAP.to_string();
+        let mut bindings = Vec::new();
+        let mut corner_cutting = CORNER_CUTTING;
         for (key, value) in &config.vars {
             match key.as_str() {
                 "width" => {
@@ -95,9 +151,45 @@ impl Settings {
                         .parse::<f32>()
                         .unwrap_or_else(|_| panic!("Cannot parse value {} in key {}!", value, key));
                 }
+                "generator" => {
+                    generator.clone_from(value);
+                }
+                "renderer" => {
+                    render_mode = match value.as_str() {
+                        "tiles" => RenderMode::Tiles,
+                        "glyphs" => RenderMode::Glyphs,
+                        _ => panic!("Cannot parse value {} in key {}!", value, key),
+                    };
+                }
+                "camera_follow" => {
+                    camera_follow = match value.as_str() {
+                        "locked" => FollowMode::Locked,
+                        "deadzone" => FollowMode::Deadzone,
+                        "free" => FollowMode::Free,
+                        _ => panic!("Cannot parse value {} in key {}!", value, key),
+                    };
+                }
+                "atlases" => {
+                    atlases = parse_list(value);
+                }
+                "corner_cutting" => {
+                    corner_cutting = value
+                        .parse::<bool>()
+                        .unwrap_or_else(|_| panic!("Cannot parse value {} in key {}!", value, key));
+                }
+                "keymap" => {
+                    keymap_preset.clone_from(value);
+                }
+                _ if key.starts_with("key.") => bindings.push(parse_binding(key, value)),
                 _ => error!("unknown key {}", key),
             }
         }
+        // Single bindings override the preset wherever they are in the file.
+        let mut keymap = Keymap::preset(&keymap_preset)
+            .unwrap_or_else(|| panic!("Cannot parse value {} in key keymap!", keymap_preset));
+        for (action, keys) in bindings {
+            keymap.bind(action, keys);
+        }
         Self {
             _config: Some(config),
             width,
@@ -105,6 +197,12 @@ impl Settings {
             gen_param,
             scroll_speed,
             zoom_speed,
+            generator,
+            render_mode,
+            camera_follow,
+            atlases,
+            keymap,
+            corner_cutting,
         }
     }
 }
@@ -118,6 +216,12 @@ impl Default for Settings {
             gen_param: GEN_PARAM,
             scroll_speed: SCROLL_SPEED,
             zoom_speed: ZOOM_SPEED,
+            generator: GENERATOR.to_string(),
+            render_mode: RENDER_MODE,
+            camera_follow: CAMERA_FOLLOW,
+            atlases: parse_list(ATLASES),
+            keymap: Keymap::preset(KEYMAP).expect("default keymap preset exists"),
+            corner_cutting: CORNER_CUTTING,
         }
     }
 }